@@ -0,0 +1,348 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::BinaryHeap;
+use std::mem::size_of;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use crate::astar::position_astar::*;
+use crate::bottle::*;
+use crate::budget::*;
+use crate::identity::*;
+use crate::rules::*;
+use crate::stats::*;
+use crate::traits::position::*;
+
+/// Weights are applied in thousandths, so priorities stay integers
+const WEIGHT_SCALE: f64 = 1000.0;
+
+/// A position waiting in the open list, ordered by its weighted priority `g + w·h`
+struct Queued {
+    priority: u64,
+    position: Rc<PositionAstar>,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Prioritize lower weighted cost, then the deeper position, which is closer to a goal
+        other.priority.cmp(&self.priority)
+            .then(self.position.currentCost.cmp(&other.position.currentCost))
+    }
+}
+
+/// Weighted A*: expands positions in order of `g + w·h` instead of `g + h`. With a weight above 1
+/// the search heads for the goal more greedily and finds a solution much faster, at most `w` times
+/// longer than the shortest one.
+///
+/// In anytime mode the search goes on after the first solution, looking for shorter ones until it
+/// proves that the best one found is the shortest possible. Positions whose unweighted projected
+/// cost reaches the length of the best solution are dropped, and positions reached again with fewer
+/// moves are reopened, so the lowest projected cost in the open list is a proven lower bound.
+pub struct AnytimeAstar {
+    heap: BinaryHeap<Queued>,
+    // Fewest moves every position seen so far was reached with
+    bestCosts: IdentityMap<u32>,
+    // How many open positions there are per unweighted projected cost, the lowest being the lower bound
+    openCosts: BTreeMap<u32, usize>,
+    weight: u64, // In thousandths
+    // The shortest solution found so far
+    incumbent: Option<Rc<PositionAstar>>,
+    stats: SearchStats,
+    limits: SearchLimits,
+    // The expanded position closest to the goal according to the heuristic, reported when a limit is hit
+    best: Rc<PositionAstar>,
+    heuristic: Heuristic,
+    rules: Rules,
+    progress: Progress,
+}
+
+impl AnytimeAstar {
+    /// Constructs a new AnytimeAstar instance.
+    ///
+    /// # Arguments
+    /// * `initialPosition` - The starting point of the search.
+    /// * `weight` - Factor applied to the heuristic, at least 1. A weight of 1 is plain A*.
+    pub fn new(initialPosition: PositionAstar, weight: f64) -> Self {
+        let mut bestCosts= IdentityMap::new(initialPosition.getBottles());
+        bestCosts.insertLower(initialPosition.getBottles(), 0);
+        let root= Rc::new(initialPosition);
+        let mut search= Self { heap: BinaryHeap::new(),
+                               bestCosts,
+                               openCosts: BTreeMap::new(),
+                               weight: (weight.max(1.0) * WEIGHT_SCALE).round() as u64,
+                               incumbent: None,
+                               stats: SearchStats::default(),
+                               limits: SearchLimits::default(),
+                               best: root.clone(),
+                               heuristic: Heuristic::default(),
+                               rules: Rules::default(),
+                               progress: Progress::default() };
+        if root.isSolved() {
+            search.incumbent= Some(root);
+        } else {
+            search.open(root);
+        }
+        search
+    }
+
+    /// Guides the search with another heuristic than `Heuristic::Towers`. Restarts the search from the
+    /// initial position, so it should be called before solving.
+    pub fn setHeuristic(&mut self, heuristic: Heuristic) {
+        let mut root= self.best.clone();
+        while let Some(previous)= &root.previous {
+            root= previous.clone();
+        }
+        let (limits, rules, progress)= (self.limits, self.rules, self.progress);
+        let weight= self.weight as f64 / WEIGHT_SCALE;
+        *self= AnytimeAstar::new(PositionAstar::newWithHeuristic(root.getBottles().clone(), heuristic, &self.rules), weight);
+        self.limits= limits;
+        self.rules= rules;
+        self.progress= progress;
+        self.heuristic= heuristic;
+    }
+
+    /// Chooses which pours are made, by default with pruning. Restarts the search like `setHeuristic`,
+    /// as the estimates may depend on the rules.
+    pub fn setRules(&mut self, rules: Rules) {
+        self.rules= rules;
+        self.setHeuristic(self.heuristic);
+    }
+
+    /// Caps the resources the search may use. Unlimited by default.
+    pub fn setLimits(&mut self, limits: SearchLimits) {
+        self.limits= limits;
+    }
+
+    /// Prints the counters of the search to stderr every `interval` while it runs. Quiet by default.
+    pub fn setProgress(&mut self, interval: Option<Duration>) {
+        self.progress= Progress::new(interval);
+    }
+
+    /// Returns the counters of the search so far
+    pub fn getStats(&self) -> SearchStats {
+        self.stats
+    }
+
+    /// Returns the proven minimum number of moves of any solution: the lowest projected cost of an
+    /// open position, or the length of the best solution if that is lower.
+    pub fn getLowerBound(&self) -> u32 {
+        let open= self.openCosts.keys().next().copied().unwrap_or(u32::MAX);
+        self.incumbent.as_ref().map_or(open, |incumbent| open.min(incumbent.currentCost))
+    }
+
+    /// Executes weighted A* until the first solution.
+    ///
+    /// # Returns
+    /// `SearchOutcome::Solved` with the sequence of positions of the first solution found,
+    /// `SearchOutcome::Exhausted` if no solution is possible, or `SearchOutcome::BudgetExceeded` with the
+    /// path to the most promising position if a limit was hit first.
+    pub fn solve(&mut self) -> SearchOutcome {
+        self.run(false, |_, _, _| {})
+    }
+
+    /// Keeps searching for shorter solutions until the best one is proven to be the shortest, the
+    /// search space is exhausted or a limit is hit.
+    ///
+    /// # Arguments
+    /// * `onSolution` - Called with every solution shorter than the ones before, the lower bound
+    ///   proven at that time and the counters of the search so far.
+    ///
+    /// # Returns
+    /// `SearchOutcome::Solved` with the shortest solution found, even if a limit stopped the search
+    /// before it was proven optimal. `getLowerBound` tells whether it was. Otherwise the same as `solve`.
+    /// May follow `solve` to improve on its solution.
+    pub fn improve(&mut self, onSolution: impl FnMut(&[Rc<dyn Position>], u32, SearchStats)) -> SearchOutcome {
+        self.run(true, onSolution)
+    }
+
+    fn run(&mut self, anytime: bool, onSolution: impl FnMut(&[Rc<dyn Position>], u32, SearchStats)) -> SearchOutcome {
+        let start= Instant::now();
+        let result= self.search(anytime, start, onSolution);
+        self.stats.recordPeaks(self.heap.len(), self.getMemoryEstimate());
+        self.stats.elapsed += start.elapsed();
+        result
+    }
+
+    fn search(&mut self, anytime: bool, start: Instant, mut onSolution: impl FnMut(&[Rc<dyn Position>], u32, SearchStats)) -> SearchOutcome {
+        while let Some(Queued { position: candidate, .. })= self.heap.pop() {
+            self.close(&candidate);
+            if candidate.isSolved() {
+                // Without anytime mode goals are queued like any other position, so the first one
+                // popped is at most `w` times longer than the shortest
+                if self.incumbent.as_ref().is_none_or(|incumbent| candidate.currentCost < incumbent.currentCost) {
+                    self.incumbent= Some(candidate.clone());
+                    if anytime {
+                        onSolution(&AnytimeAstar::buildSolutionVector(candidate.clone()), self.getLowerBound(), self.getRunningStats(start));
+                    }
+                }
+                if !anytime {
+                    return SearchOutcome::Solved(AnytimeAstar::buildSolutionVector(candidate));
+                }
+                continue;
+            }
+            if self.incumbent.as_ref().is_some_and(|incumbent| candidate.totalProjectedCost >= incumbent.currentCost) {
+                continue;
+            }
+            if let Some(limit)= self.limits.check(&self.stats, start, || self.getMemoryEstimate()) {
+                return match &self.incumbent {
+                    Some(incumbent) => SearchOutcome::Solved(AnytimeAstar::buildSolutionVector(incumbent.clone())),
+                    None => SearchOutcome::BudgetExceeded(limit, AnytimeAstar::buildSolutionVector(self.best.clone())),
+                };
+            }
+            if self.stats.expanded.is_multiple_of(LIMIT_CHECK_INTERVAL) {
+                self.stats.recordPeaks(self.heap.len(), self.getMemoryEstimate());
+                self.progress.report("Anytime A*", &self.stats, start);
+            }
+            if candidate.getHeuristic() < self.best.getHeuristic() {
+                self.best= candidate.clone();
+            }
+            self.stats.expanded += 1;
+            for position in PositionAstar::getNextPossiblePositions(&candidate, self.heuristic, &self.rules) {
+                self.stats.generated += 1;
+                if position.isDeadEnd() {
+                    continue;
+                }
+                let bound= self.incumbent.as_ref().map_or(u32::MAX, |incumbent| incumbent.currentCost);
+                if position.totalProjectedCost >= bound || !self.bestCosts.insertLower(position.getBottles(), position.currentCost) {
+                    self.stats.duplicates += 1;
+                    continue;
+                }
+                if anytime && position.isSolved() {
+                    // Goals are recognized as soon as they are generated, so improvements show up early
+                    self.incumbent= Some(position.clone());
+                    onSolution(&AnytimeAstar::buildSolutionVector(position), self.getLowerBound(), self.getRunningStats(start));
+                    continue;
+                }
+                self.open(position);
+            }
+            self.stats.peakFrontier= self.stats.peakFrontier.max(self.heap.len());
+            if self.incumbent.as_ref().is_some_and(|incumbent| self.getLowerBound() >= incumbent.currentCost) {
+                break;
+            }
+        }
+        match &self.incumbent {
+            Some(incumbent) => {
+                // Every open position would need at least as many moves, so the incumbent is optimal
+                self.heap.clear();
+                self.openCosts.clear();
+                SearchOutcome::Solved(AnytimeAstar::buildSolutionVector(incumbent.clone()))
+            },
+            None => SearchOutcome::Exhausted,
+        }
+    }
+
+    /// Returns the counters of a search started at `start` that is still running
+    fn getRunningStats(&self, start: Instant) -> SearchStats {
+        SearchStats { elapsed: self.stats.elapsed + start.elapsed(), ..self.stats }
+    }
+
+    /// Adds a position to the open list
+    fn open(&mut self, position: Rc<PositionAstar>) {
+        let priority= position.currentCost as u64 * WEIGHT_SCALE as u64 + position.getHeuristic() as u64 * self.weight;
+        *self.openCosts.entry(position.totalProjectedCost).or_insert(0) += 1;
+        self.heap.push(Queued { priority, position });
+    }
+
+    /// Removes a position taken from the open list from the lower bound bookkeeping
+    fn close(&mut self, position: &PositionAstar) {
+        if let Some(count)= self.openCosts.get_mut(&position.totalProjectedCost) {
+            *count -= 1;
+            if *count == 0 {
+                self.openCosts.remove(&position.totalProjectedCost);
+            }
+        }
+    }
+
+    /// Approximates the memory held by the search: every position seen keeps its key and cost in
+    /// `bestCosts` and, while it is open or has open descendants, the position itself.
+    fn getMemoryEstimate(&self) -> usize {
+        let bottles= self.best.getBottles().len();
+        let position= size_of::<PositionAstar>() + 2 * size_of::<usize>() + bottles * size_of::<Bottle>();
+        self.bestCosts.len() * (self.bestCosts.getBytesPerPosition() + position) + self.heap.len() * size_of::<Queued>()
+    }
+
+    /// Constructs a vector of `Position` objects representing the path from the initial position to
+    /// the given position.
+    fn buildSolutionVector(position: Rc<PositionAstar>) -> Vec<Rc<dyn Position>> {
+        let mut result: Vec<Rc<dyn Position>>= Vec::new();
+        let mut current= Some(position);
+        while let Some(position)= current {
+            current= position.previous.clone();
+            result.push(position);
+        }
+        result.reverse();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::astar::astar::*;
+
+    fn bottles() -> Vec<Bottle> {
+        ["ABCD", "CDBA", "BADC", "DCAB", "    ", "    "].iter().map(|bottle| Bottle::new(bottle.as_bytes())).collect()
+    }
+
+    fn moveCount(outcome: SearchOutcome) -> u32 {
+        match outcome {
+            SearchOutcome::Solved(positions) => positions.len() as u32 - 1,
+            _ => panic!("The puzzle is solvable"),
+        }
+    }
+
+    #[test]
+    fn weightedWithinBound() {
+        let optimal= moveCount(Astar::new(PositionAstar::new(bottles())).solve());
+        assert_eq!(moveCount(AnytimeAstar::new(PositionAstar::new(bottles()), 1.0).solve()), optimal);
+        for weight in [1.5, 3.0, 10.0] {
+            let mut weighted= AnytimeAstar::new(PositionAstar::new(bottles()), weight);
+            let moves= moveCount(weighted.solve());
+            assert!(moves >= optimal && moves as f64 <= optimal as f64 * weight);
+            assert!(weighted.getLowerBound() <= optimal);
+        }
+    }
+
+    #[test]
+    fn anytimeConvergesToOptimal() {
+        let optimal= moveCount(Astar::new(PositionAstar::new(bottles())).solve());
+        let mut anytime= AnytimeAstar::new(PositionAstar::new(bottles()), 5.0);
+        let mut reports= Vec::new();
+        let outcome= anytime.improve(|positions, lowerBound, _| reports.push((positions.len() as u32 - 1, lowerBound)));
+        assert_eq!(moveCount(outcome), optimal);
+        assert_eq!(anytime.getLowerBound(), optimal);
+        assert!(!reports.is_empty());
+        // Every report improves on the previous one and never claims more than the optimum is needed
+        assert!(reports.windows(2).all(|pair| pair[1].0 < pair[0].0));
+        assert!(reports.iter().all(|&(moves, lowerBound)| lowerBound <= optimal && moves >= optimal));
+        // Improving after a weighted solution picks up where it left off
+        let mut weighted= AnytimeAstar::new(PositionAstar::new(bottles()), 5.0);
+        let first= moveCount(weighted.solve());
+        assert_eq!(moveCount(weighted.improve(|positions, _, _| assert!((positions.len() as u32 - 1) < first))), optimal);
+    }
+
+    #[test]
+    fn anytimeStoppedByLimit() {
+        let mut anytime= AnytimeAstar::new(PositionAstar::new(bottles()), 5.0);
+        anytime.setLimits(SearchLimits { maxExpanded: Some(1), ..SearchLimits::default() });
+        assert!(matches!(anytime.improve(|_, _, _| {}), SearchOutcome::BudgetExceeded(Limit::Expanded, _)));
+        let unsolvable= vec![Bottle::new(b"AB"), Bottle::new(b"BA")];
+        assert!(matches!(AnytimeAstar::new(PositionAstar::new(unsolvable), 2.0).improve(|_, _, _| {}), SearchOutcome::Exhausted));
+    }
+}
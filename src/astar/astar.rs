@@ -1,48 +1,164 @@
 use std::rc::Rc;
-use std::collections::HashSet;
+use std::time::{Duration, Instant};
 use std::collections::BinaryHeap;
+use std::mem::size_of;
 use crate::astar::position_astar::*;
+use crate::bottle::*;
+use crate::budget::*;
+use crate::identity::*;
+use crate::rules::*;
+use crate::stats::*;
 use crate::traits::position::*;
 
 pub struct Astar {
     heap: BinaryHeap<Rc<PositionAstar>>,
-    // Collision sets to make sure all positions recorded are truly unique (barring bottle
-    // permutations). 
-    uniquePositions: HashSet<Vec<u8>>,
+    // Fewest moves every position seen so far was reached with (barring bottle permutations). A
+    // position reached again with fewer moves is queued again, otherwise the solution could be longer
+    // than the shortest one
+    bestCosts: IdentityMap<u32>,
+    stats: SearchStats,
+    limits: SearchLimits,
+    // The expanded position closest to the goal according to the heuristic, reported when a limit is hit
+    best: Rc<PositionAstar>,
+    heuristic: Heuristic,
+    rules: Rules,
+    progress: Progress,
 }
 
 impl Astar {
-    /// Constructs a new Astar instance with an initial position, initializes the `uniquePositions` map,
+    /// Constructs a new Astar instance with an initial position, initializes the `bestCosts` map,
     /// and calculates the initial syntropy based on the identity of the initial position.
     ///
     /// # Arguments
     /// * `initialPosition` - The starting point of the BFS.
     pub fn new(initialPosition: PositionAstar) -> Self {
-        let uniquePositions= HashSet::from_iter(std::iter::once(initialPosition.getIdentity()));
-        let heap= BinaryHeap::from_iter(std::iter::once(Rc::new(initialPosition)));
+        let mut bestCosts= IdentityMap::new(initialPosition.getBottles());
+        bestCosts.insertLower(initialPosition.getBottles(), 0);
+        let best= Rc::new(initialPosition);
+        let heap= BinaryHeap::from_iter(std::iter::once(best.clone()));
         Self { heap,
-               uniquePositions }
+               bestCosts,
+               stats: SearchStats::default(),
+               limits: SearchLimits::default(),
+               best,
+               heuristic: Heuristic::default(),
+               rules: Rules::default(),
+               progress: Progress::default() }
+    }
+
+    /// Guides the search with another heuristic than `Heuristic::Towers`. Restarts the search from the
+    /// initial position, so it should be called before solving.
+    pub fn setHeuristic(&mut self, heuristic: Heuristic) {
+        let root= self.buildRoot(heuristic);
+        let (limits, rules, progress)= (self.limits, self.rules, self.progress);
+        *self= Astar::new(root);
+        self.limits= limits;
+        self.rules= rules;
+        self.progress= progress;
+        self.heuristic= heuristic;
+    }
+
+    /// Chooses which pours are made, by default with pruning. Restarts the search like `setHeuristic`,
+    /// as the estimates may depend on the rules.
+    pub fn setRules(&mut self, rules: Rules) {
+        self.rules= rules;
+        self.setHeuristic(self.heuristic);
+    }
+
+    /// Returns the initial position of the search, estimated by `heuristic`
+    fn buildRoot(&self, heuristic: Heuristic) -> PositionAstar {
+        let mut root= self.best.clone();
+        while let Some(previous)= &root.previous {
+            root= previous.clone();
+        }
+        PositionAstar::newWithHeuristic(root.getBottles().clone(), heuristic, &self.rules)
+    }
+
+    /// Caps the resources the search may use. Unlimited by default.
+    pub fn setLimits(&mut self, limits: SearchLimits) {
+        self.limits= limits;
+    }
+
+    /// Prints the counters of the search to stderr every `interval` while it runs. Quiet by default.
+    pub fn setProgress(&mut self, interval: Option<Duration>) {
+        self.progress= Progress::new(interval);
     }
 
     /// Executes the A* algorithm to find a solution.
     ///
     /// # Returns
-    /// `Option<Vec<Position>>` representing the sequence of moves to solve the puzzle if a solution is found.
-    /// `None` if no solution is possible.
-    pub fn solve(&mut self) -> Option<Vec<Rc<dyn Position>>> {
+    /// `SearchOutcome::Solved` with the sequence of positions solving the puzzle if a solution is found,
+    /// `SearchOutcome::Exhausted` if no solution is possible, or `SearchOutcome::BudgetExceeded` with the
+    /// path to the most promising position if a limit was hit first.
+    pub fn solve(&mut self) -> SearchOutcome {
+        self.solveUntil(PositionAstar::isSolved)
+    }
+
+    /// Executes the A* algorithm until it reaches a position satisfying a custom goal.
+    /// The path is only guaranteed to be the shortest if the heuristic never overestimates the
+    /// distance to the goal.
+    ///
+    /// # Arguments
+    /// * `isGoal` - Returns `true` for positions where the search should stop.
+    ///
+    /// # Returns
+    /// The `SearchOutcome`, where `Solved` holds the sequence of moves to the first goal reached.
+    pub fn solveUntil(&mut self, isGoal: impl Fn(&PositionAstar) -> bool) -> SearchOutcome {
+        let start= Instant::now();
+        let result= self.search(isGoal, start);
+        self.stats.recordPeaks(self.heap.len(), self.getMemoryEstimate());
+        self.stats.elapsed += start.elapsed();
+        result
+    }
+
+    fn search(&mut self, isGoal: impl Fn(&PositionAstar) -> bool, start: Instant) -> SearchOutcome {
         while let Some(candidate)= self.heap.pop() {
-            if candidate.isSolved() {
-                return Some(Astar::buildSolutionVector(candidate));
+            if isGoal(&candidate) {
+                return SearchOutcome::Solved(Astar::buildSolutionVector(candidate));
+            }
+            // Queued again since, with fewer moves
+            if self.bestCosts.get(candidate.getBottles()).is_some_and(|&cost| cost < candidate.currentCost) {
+                continue;
+            }
+            if let Some(limit)= self.limits.check(&self.stats, start, || self.getMemoryEstimate()) {
+                return SearchOutcome::BudgetExceeded(limit, Astar::buildSolutionVector(self.best.clone()));
             }
-            for position in PositionAstar::getNextPossiblePositions(&candidate) {
-                let candidateIdentity= position.getIdentity();
-                if !self.uniquePositions.insert(candidateIdentity) {
+            if self.stats.expanded.is_multiple_of(LIMIT_CHECK_INTERVAL) {
+                self.stats.recordPeaks(self.heap.len(), self.getMemoryEstimate());
+                self.progress.report("A*", &self.stats, start);
+            }
+            if candidate.getHeuristic() < self.best.getHeuristic() {
+                self.best= candidate.clone();
+            }
+            self.stats.expanded += 1;
+            for position in PositionAstar::getNextPossiblePositions(&candidate, self.heuristic, &self.rules) {
+                self.stats.generated += 1;
+                // No move leads out of a dead end, so there is nothing to gain from queueing it
+                if position.isDeadEnd() {
                     continue;
-                } 
+                }
+                if !self.bestCosts.insertLower(position.getBottles(), position.currentCost) {
+                    self.stats.duplicates += 1;
+                    continue;
+                }
                 self.heap.push(position);
             }
+            self.stats.peakFrontier= self.stats.peakFrontier.max(self.heap.len());
         }
-        None
+        SearchOutcome::Exhausted
+    }
+
+    /// Approximates the memory held by the search: every unique position keeps its identity in
+    /// `bestCosts` and, while it is open or has open descendants, the position itself.
+    fn getMemoryEstimate(&self) -> usize {
+        let bottles= self.best.getBottles().len();
+        let position= size_of::<PositionAstar>() + 2 * size_of::<usize>() + bottles * size_of::<Bottle>();
+        self.bestCosts.len() * (self.bestCosts.getBytesPerPosition() + position)
+    }
+
+    /// Returns the counters of the search so far
+    pub fn getStats(&self) -> SearchStats {
+        self.stats
     }
 
     /// Constructs a vector of `Position` objects representing the path from the initial position to
@@ -1,2 +1,3 @@
 pub mod position_astar;
 pub mod astar;
+pub mod anytime;
@@ -1,12 +1,137 @@
 use std::collections::HashSet;
-use std::collections::HashMap;
 use std::cmp::Ordering;
 use std::fmt::Write;
 use std::fmt;
 use std::rc::Rc;
 use crate::bottle::*;
+use crate::rules::*;
 use crate::traits::position::*;
 
+/// Estimate of the moves left from a position the puzzle can no longer be solved from. Far above
+/// any real distance, yet adding a move count to it can't overflow
+pub const DEAD_END: u32= u32::MAX / 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// The estimates of the moves left that guide the A* family of searches. All of them are admissible
+/// and consistent, so the solutions stay the shortest; the stronger ones expand fewer positions but
+/// take longer to compute per position.
+pub enum Heuristic {
+    #[default]
+    Towers, // Color towers that must move: all but one per color at the bottom of some bottle
+    Parking, // `Towers`, plus one if no pour lowers it, e.g. a buried color first needs an empty bottle
+    Lookahead, // The lowest `Parking` estimate after one more pour, plus that pour
+}
+
+impl Heuristic {
+    pub const ALL: [Heuristic; 3]= [Heuristic::Towers, Heuristic::Parking, Heuristic::Lookahead];
+
+    /// Returns the name of the heuristic as used on the command line
+    pub fn getName(&self) -> &'static str {
+        match self {
+            Heuristic::Towers => "towers",
+            Heuristic::Parking => "parking",
+            Heuristic::Lookahead => "lookahead",
+        }
+    }
+
+    /// Returns the heuristic with the given command line name, if any
+    pub fn fromName(name: &str) -> Option<Heuristic> {
+        Heuristic::ALL.into_iter().find(|heuristic| heuristic.getName() == name)
+    }
+
+    /// Estimates the number of moves needed to solve a position, never more than the actual number.
+    /// `Towers` and `Parking` hold for every pour rule, as moving only part of a tower never lowers
+    /// the tower count; `Lookahead` tries the pours `rules` allow.
+    ///
+    /// # Returns
+    /// The estimate, 0 for solved positions and `DEAD_END` if no move is possible in an unsolved one.
+    pub fn estimate(&self, bottles: &[Bottle], rules: &Rules) -> u32 {
+        match self {
+            Heuristic::Towers => Heuristic::countTowers(bottles),
+            Heuristic::Parking => Heuristic::countParking(bottles),
+            Heuristic::Lookahead => Heuristic::lookAhead(bottles, rules),
+        }
+    }
+
+    /// Every pour moves the top tower of one bottle, so it joins at most two towers. The towers of a
+    /// color must all be joined, and a color that is no bottle's bottom color must also be poured into
+    /// an empty bottle once.
+    fn countTowers(bottles: &[Bottle]) -> u32 {
+        let mut heuristic: u32= 0;
+        let mut set = HashSet::new();
+        // Count how many color towers minus the bottom color are in the bottles
+        // This is the minimum moves we need to make to move non-bottom colors
+        for b in bottles {
+            if b.isEmpty() {
+                continue;
+            }
+            heuristic += (b.getColorTowers() as u32) - 1;
+            // If a bottom color occurs more than once, it's at least one move
+            if !set.insert(b.content[0]) {
+                heuristic += 1;
+            }
+        }
+        heuristic
+    }
+
+    /// A pour lowers the tower count only if it moves a whole tower onto the same color without
+    /// emptying the last bottle of that bottom color, or starts the first bottle of a color in an
+    /// empty one. If no pour does, one move more than the tower count is needed.
+    fn countParking(bottles: &[Bottle]) -> u32 {
+        if isSolvedPosition(bottles) {
+            return 0;
+        }
+        let mut bottomCounts= [0u8; 256];
+        for bottle in bottles.iter().filter(|bottle| !bottle.isEmpty()) {
+            bottomCounts[bottle.content[0] as usize] += 1;
+        }
+        let towers= Heuristic::countTowers(bottles);
+        let mut canPour= false;
+        for (j, source) in bottles.iter().enumerate() {
+            if source.isEmpty() {
+                continue;
+            }
+            let sourceTop= source.getTopIndex();
+            let color= source.content[sourceTop];
+            let towerHeight= source.content[..=sourceTop].iter().rev().take_while(|&&slot| slot == color).count();
+            let lastTower= towerHeight == sourceTop + 1;
+            for (i, destination) in bottles.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                if destination.isEmpty() {
+                    canPour= true;
+                    if bottomCounts[color as usize] == 0 && towerHeight <= destination.getCapacity() {
+                        return towers;
+                    }
+                    continue;
+                }
+                let destinationTop= destination.getTopIndex();
+                let space= destination.getCapacity() - 1 - destinationTop;
+                if space > 0 && destination.content[destinationTop] == color {
+                    canPour= true;
+                    if space >= towerHeight && (!lastTower || bottomCounts[color as usize] >= 2) {
+                        return towers;
+                    }
+                }
+            }
+        }
+        if canPour { towers + 1 } else { DEAD_END }
+    }
+
+    /// Tries every pour and keeps the one leading to the lowest `Parking` estimate
+    fn lookAhead(bottles: &[Bottle], rules: &Rules) -> u32 {
+        if isSolvedPosition(bottles) {
+            return 0;
+        }
+        let mut best= DEAD_END;
+        PositionAstar::forEachPour(bottles, rules, |next| {
+            best= best.min(Heuristic::countParking(&next).saturating_add(1));
+        });
+        best
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// Represents a specific arrangement or position of bottles.
 pub struct PositionAstar {
@@ -21,7 +146,13 @@ pub struct PositionAstar {
 impl PositionAstar {
 
     pub fn new(bottles: Vec<Bottle>) -> PositionAstar {
-        PositionAstar::newChild(bottles, None, 0, 0)
+        PositionAstar::newWithHeuristic(bottles, Heuristic::default(), &Rules::default())
+    }
+
+    /// Creates the initial position of a search guided by `heuristic` under `rules`
+    pub fn newWithHeuristic(bottles: Vec<Bottle>, heuristic: Heuristic, rules: &Rules) -> PositionAstar {
+        let estimate= heuristic.estimate(&bottles, rules);
+        PositionAstar::newChild(bottles, None, 0, estimate)
     }
 
     pub fn newChild(
@@ -33,111 +164,83 @@ impl PositionAstar {
         Self { bottles, previous, currentCost, totalProjectedCost: currentCost + projectedCost }
     }
 
-    pub fn getIdentity(&self) -> Vec<u8> {
-        let mut cloned_bottles = self.bottles.clone();
-        cloned_bottles.sort();
-        cloned_bottles
-            .into_iter()
-            .flat_map(|bottle| bottle.content.into_iter())
-            .collect()
+    #[inline]
+    /// Returns the estimated number of moves left to solve this position
+    pub fn getHeuristic(&self) -> u32 {
+        self.totalProjectedCost - self.currentCost
     }
 
     #[inline]
     /// # Returns
     /// `true` if all bottles are solved, otherwise `false`.
     pub fn isSolved(&self) -> bool {
-        self.bottles.iter().all(|bottle| bottle.isSolved())
+        isSolvedPosition(&self.bottles)
     }
 
-    /// Validates that all characters (colors) in the bottles appear exactly 4 times,
-    /// ensuring the position meets game rules.
+    /// Validates that every character (color) in the bottles fills a bottle of one of the sizes
+    /// exactly, ensuring the position meets game rules. See `checkColorCounts`.
     ///
     /// # Returns
     /// `Ok(())` if the validation passes, otherwise `Err` with a message detailing inconsistencies.
     pub fn isValid(&self) -> Result<(), String> {
-        let mut char_count = HashMap::new();
-        // Iterate over each array and then each character in the array
-        for bottle in &self.bottles {
-            for &ch in bottle.content.iter() {
-                if ch != ' ' as u8 {
-                    *char_count.entry(ch).or_insert(0) += 1;
-                }
-            }
-        }
-
-        if char_count.values().all(|&count| count == 4) {
-            return Ok(());
-        }
-        let mut error= String::new();
-        error.push_str("Error, the position is invalid! Please count the characters: \n");
-        for (&character, &count) in char_count.iter() {
-            if count != 4 {
-                write!(error, "Character: '{}', Count: {}\n", character, count).unwrap();
-            }
-        }
-        Err(error)
+        checkColorCounts(&self.bottles)
     }
 
-    /// Calculates the syntropy value for a given identity of a position.
-    /// Syntropy measures the orderliness based on consecutive identical slots in each bottle.
-    ///
-    /// # Arguments
-    /// * `identity` - A reference to the identity vector of a position.
-    ///
-    /// # Returns
-    /// An usize representing the syntropy value.
-    fn getHeuristic(bottles: &Vec<Bottle>) -> u32 {
-        let mut heuristic: u32= 0;
-        let mut set = HashSet::new();
-        // Count how many color towers minus the bottom color are in the bottles
-        // This is the minimum moves we need to make to move non-bottom colors
-        for b in bottles {
-            if b.isEmpty() {
-                continue;
-            }
-            heuristic += (b.getColorTowers() as u32) - 1;
-            // If a bottom color occurs more than once, it's at least one move
-            if !set.insert(b.content[0]) {
-                heuristic += 1;
-            }
-        }
-        heuristic
+    #[inline]
+    /// Returns `true` if no move can lead to a solution any more
+    pub fn isDeadEnd(&self) -> bool {
+        self.getHeuristic() >= DEAD_END
     }
 
     /// Generates all valid next positions reachable in one move by attempting to transfer contents
     /// between each pair of bottles.
     ///
     /// # Arguments
-    /// * `targetSyntropy` - The syntropy value of a puzzle solution (predictable and the same for
-    /// all solutions
+    /// * `parent` - The position to move from.
+    /// * `heuristic` - Estimates the moves left from each new position.
+    /// * `rules` - Which pours are made.
     ///
     /// # Returns
     /// A vector of `Position` instances representing all possible next states.
-    pub fn getNextPossiblePositions(parent: &Rc<PositionAstar>) -> Vec<Rc<PositionAstar>> {
+    pub fn getNextPossiblePositions(parent: &Rc<PositionAstar>, heuristic: Heuristic, rules: &Rules) -> Vec<Rc<PositionAstar>> {
         let mut result= Vec::new();
-        let mut newBottles= parent.bottles.clone();
+        PositionAstar::forEachPour(&parent.bottles, rules, |newBottles| {
+            let newHeuristic= heuristic.estimate(&newBottles, rules);
+            result.push(Rc::new(PositionAstar::newChild(newBottles, Some(parent.clone()), parent.currentCost + 1, newHeuristic)));
+        });
+        result
+    }
+
+    /// Calls `visit` with the bottles after every possible transfer between two of them that `rules`
+    /// don't skip, with their locks updated for the move
+    fn forEachPour(bottles: &[Bottle], rules: &Rules, mut visit: impl FnMut(Vec<Bottle>)) {
+        let mut newBottles= bottles.to_vec();
         let bottleNum= newBottles.len();
+        let locked= bottles.iter().any(Bottle::isLocked);
         for i in 0..bottleNum {
             for j in 0..bottleNum {
-                if i < j {
+                let poured= if i < j {
                     let (left, right) = newBottles.split_at_mut(j);
-                    if left[i].fillFrom(&mut right[0]) {
-                        let newHeuristic= PositionAstar::getHeuristic(&newBottles);
-                        result.push(Rc::new(PositionAstar::newChild(newBottles, Some(parent.clone()), parent.currentCost + 1, newHeuristic)));
-                        newBottles= parent.bottles.clone();
-                    }
+                    rules.pour(&mut left[i], &mut right[0])
                 }
                 else if i > j {
                     let (left, right) = newBottles.split_at_mut(i);
-                    if right[0].fillFrom(&mut left[j]) {
-                        let newHeuristic= PositionAstar::getHeuristic(&newBottles);
-                        result.push(Rc::new(PositionAstar::newChild(newBottles, Some(parent.clone()), parent.currentCost + 1, newHeuristic)));
-                        newBottles= parent.bottles.clone();
+                    rules.pour(&mut right[0], &mut left[j])
+                }
+                else {
+                    false
+                };
+                if poured {
+                    if !rules.isRedundant(bottles, j, i) {
+                        if locked {
+                            passMove(&mut newBottles);
+                        }
+                        visit(newBottles);
                     }
+                    newBottles= bottles.to_vec();
                 }
             }
         }
-        result
     }
 
 }
@@ -145,7 +248,7 @@ impl PositionAstar {
 impl Position for PositionAstar {
 
     fn getBottles(&self) -> &Vec<Bottle> {
-        return &self.bottles;
+        &self.bottles
     }
 
     /// Creates a string representation of the position,
@@ -164,14 +267,20 @@ impl Position for PositionAstar {
                 return Err("Two positions have different length!".to_string());
             }
         }
-        for i in (0..4).rev() {
+        let height= self.bottles.iter().map(|bottle| bottle.getCapacity()).max().unwrap_or(0);
+        for i in (0..height).rev() {
             for j in 0..length {
-                if j > 0 && j % ((length + 2) / 3) == 0 {
+                if j > 0 && j % length.div_ceil(3) == 0 {
                     out.push_str("  ");
                 }
                 let ourBottle= &self.bottles[j];
+                // Shorter bottles have no slots up here
+                if i >= ourBottle.getCapacity() {
+                    out.push_str("   ");
+                    continue;
+                }
                 // if previous exists and our bottles aren't equal, make our bottle bold
-                if let Some(_) = possiblePrevious.as_ref().filter(|p| ourBottle != &p.getBottles()[j]) {
+                if possiblePrevious.as_ref().is_some_and(|p| ourBottle.getContent() != p.getBottles()[j].getContent()) {
                     write!(out, "❚{}❚", ourBottle.content[i] as char).unwrap();
                 } else {
                     write!(out, "|{}|", ourBottle.content[i] as char).unwrap();
@@ -187,9 +296,14 @@ impl Position for PositionAstar {
 
 impl fmt::Display for PositionAstar {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for i in (0..4).rev() {
+        let height= self.bottles.iter().map(|bottle| bottle.getCapacity()).max().unwrap_or(0);
+        for i in (0..height).rev() {
             for bottle in &self.bottles {
-                write!(f, "|{}|", bottle.content[i] as char)?;
+                if i < bottle.getCapacity() {
+                    write!(f, "|{}|", bottle.content[i] as char)?;
+                } else {
+                    write!(f, "   ")?;
+                }
             }
             writeln!(f)?;
         }
@@ -217,16 +331,30 @@ impl Ord for PositionAstar {
 mod tests {
 
     use super::*;
+    use std::collections::HashMap;
     use std::collections::HashSet;
+    use std::collections::VecDeque;
+
+    impl PositionAstar {
+        /// The sorted contents of the bottles, identical for all bottle permutations
+        fn getIdentity(&self) -> Vec<u8> {
+            let mut cloned_bottles = self.bottles.clone();
+            cloned_bottles.sort();
+            cloned_bottles
+                .into_iter()
+                .flat_map(|bottle| bottle.content.into_iter().take(bottle.getCapacity()))
+                .collect()
+        }
+    }
 
     #[test]
     fn checkNextPositions() {
-        let bottle1= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let bottle2= Bottle::newChars([ 'B', 'B', 'A', ' ']);
-        let bottle4= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle5= Bottle::newChars([ 'B', 'B', 'B', ' ']);
+        let bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle5= Bottle::newChars(&[ 'B', 'B', 'B', ' ']);
         let pos1= Rc::new(PositionAstar::new(vec![bottle1, bottle2, bottle4, bottle5]));
-        let newPositions= PositionAstar::getNextPossiblePositions(&pos1);
+        let newPositions= PositionAstar::getNextPossiblePositions(&pos1, Heuristic::Towers, &Rules { pruning: false, ..Rules::default() });
         assert_eq!(newPositions.len(), 4);
         let expectedIdentities: HashSet<Vec<u8>> = Vec::from_iter([
             vec!['A', 'A', 'A', ' ', 'B', 'B', ' ', ' ', 'B', 'B', ' ', ' ', 'B', 'B', 'B', ' '],
@@ -239,31 +367,125 @@ mod tests {
                 .map(|c| c as u8) // Safe cast, as all chars are guaranteed to be ASCII
                 .collect::<Vec<u8>>() // Collect bytes into Vec<u8>
             }).collect();
-        newPositions.iter().for_each(|position| { assert_eq!(expectedIdentities.contains(&position.getIdentity()), true) });
+        newPositions.iter().for_each(|position| { assert!(expectedIdentities.contains(&position.getIdentity())) });
     }
 
     #[test]
     fn checkNumNextPositions() {
-        let bottle1= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let bottle2= Bottle::newChars([ 'B', 'B', 'A', ' ']);
-        let bottle3= Bottle::newChars([ 'A', 'A', 'A', ' ']);
-        let bottle4= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle5= Bottle::newChars([ 'B', 'B', 'B', ' ']);
+        let bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle5= Bottle::newChars(&[ 'B', 'B', 'B', ' ']);
         let pos1= Rc::new(PositionAstar::new(vec![bottle1, bottle2, bottle3, bottle4, bottle5]));
-        let newPositions= PositionAstar::getNextPossiblePositions(&pos1);
+        let newPositions= PositionAstar::getNextPossiblePositions(&pos1, Heuristic::Towers, &Rules { pruning: false, ..Rules::default() });
         assert_eq!(newPositions.len(), 8);
     }
 
+    #[test]
+    fn checkWholeRunPositions() {
+        let bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle5= Bottle::newChars(&[ 'B', 'B', 'B', ' ']);
+        let pos1= Rc::new(PositionAstar::new(vec![bottle1, bottle2, bottle3, bottle4, bottle5]));
+        let rules= Rules { pruning: false, pour: PourRule::WholeRun };
+        let newPositions= PositionAstar::getNextPossiblePositions(&pos1, Heuristic::Towers, &rules);
+        // Only the single A of the second bottle fits entirely onto the other A's
+        assert_eq!(newPositions.len(), 2);
+    }
+
     #[test]
     fn checkHeuristic() {
-        let bottle1= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let bottle2= Bottle::newChars([ 'B', 'B', 'A', ' ']);
-        let bottle3= Bottle::newChars([ 'A', 'A', 'A', ' ']);
-        let bottle4= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle5= Bottle::newChars([ 'B', 'B', 'B', ' ']);
+        let bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle5= Bottle::newChars(&[ 'B', 'B', 'B', ' ']);
         let mut bottles= vec![bottle1, bottle2, bottle3, bottle4, bottle5];
-        assert_eq!(PositionAstar::getHeuristic(&bottles), 4);
-        bottles.push(Bottle::newChars([ ' ', ' ', ' ', ' ' ]));
-        assert_eq!(PositionAstar::getHeuristic(&bottles), 4);
+        assert_eq!(Heuristic::Towers.estimate(&bottles, &Rules::default()), 4);
+        // Pouring the top A onto the first bottle joins two towers
+        assert_eq!(Heuristic::Parking.estimate(&bottles, &Rules::default()), 4);
+        bottles.push(Bottle::newChars(&[ ' ', ' ', ' ', ' ' ]));
+        assert_eq!(Heuristic::Towers.estimate(&bottles, &Rules::default()), 4);
+        // No pour joins two towers, one must go to an empty bottle first
+        let parked= toBottles(&["ABCA", "BCAB", "CABC", "    ", "    "]);
+        assert_eq!(Heuristic::Towers.estimate(&parked, &Rules::default()), 9);
+        assert_eq!(Heuristic::Parking.estimate(&parked, &Rules::default()), 10);
+        assert!(Heuristic::Lookahead.estimate(&parked, &Rules::default()) >= 10);
+        // Nothing can be poured any more
+        let stuck= toBottles(&["AB", "BA"]);
+        assert_eq!(Heuristic::Towers.estimate(&stuck, &Rules::default()), 2);
+        assert_eq!(Heuristic::Parking.estimate(&stuck, &Rules::default()), DEAD_END);
+        assert_eq!(Heuristic::Lookahead.estimate(&stuck, &Rules::default()), DEAD_END);
+        assert!(Heuristic::ALL.iter().all(|heuristic| heuristic.estimate(&toBottles(&["AA", "BB", "  "]), &Rules::default()) == 0));
+    }
+
+    fn toBottles(contents: &[&str]) -> Vec<Bottle> {
+        contents.iter().map(|content| Bottle::new(content.as_bytes())).collect()
+    }
+
+    /// The positions each position is reached from in one pour
+    type Predecessors= HashMap<Vec<Bottle>, Vec<Vec<Bottle>>>;
+
+    /// Explores every position reachable from `bottles`, keyed by their sorted bottles.
+    ///
+    /// # Returns
+    /// The positions each position is reached from in one pour, and the fewest moves solving each
+    /// position a solution can be reached from.
+    fn exactDistances(bottles: &[Bottle]) -> (Predecessors, HashMap<Vec<Bottle>, u32>) {
+        let sorted= |bottles: &[Bottle]| { let mut sorted= bottles.to_vec(); sorted.sort(); sorted };
+        let mut predecessors: Predecessors= HashMap::from([(sorted(bottles), Vec::new())]);
+        let mut queue= VecDeque::from([sorted(bottles)]);
+        while let Some(position)= queue.pop_front() {
+            PositionAstar::forEachPour(&position, &Rules { pruning: false, ..Rules::default() }, |next| {
+                let next= sorted(&next);
+                if !predecessors.contains_key(&next) {
+                    queue.push_back(next.clone());
+                }
+                predecessors.entry(next).or_default().push(position.clone());
+            });
+        }
+        let mut distances: HashMap<Vec<Bottle>, u32>= predecessors.keys()
+            .filter(|position| position.iter().all(Bottle::isSolved))
+            .map(|position| (position.clone(), 0))
+            .collect();
+        let mut queue: VecDeque<Vec<Bottle>>= distances.keys().cloned().collect();
+        while let Some(position)= queue.pop_front() {
+            let distance= distances[&position];
+            for previous in &predecessors[&position] {
+                if !distances.contains_key(previous) {
+                    distances.insert(previous.clone(), distance + 1);
+                    queue.push_back(previous.clone());
+                }
+            }
+        }
+        (predecessors, distances)
+    }
+
+    #[test]
+    fn heuristicsAdmissible() {
+        let puzzles: [&[&str]; 8]= [&["ABCA", "BCAB", "CABC", "    ", "    "], &["ABCA", "BCAB", "CABC", "    "],
+                                    &["ABCD", "DCBA", "BADC", "CDAB", "    "], &["AABB", "BBAA", "    "],
+                                    &["ABC", "BCA", "CAB", "   "], &["ABA", "BAB", "   "], &["AB", "CA", "BC", "  "], &["AB", "BA"]];
+        for puzzle in puzzles {
+            let (predecessors, distances)= exactDistances(&toBottles(puzzle));
+            for (position, previousPositions) in &predecessors {
+                let estimates= Heuristic::ALL.map(|heuristic| heuristic.estimate(position, &Rules::default()));
+                // Each heuristic is at least as strong as the one before
+                assert!(estimates.windows(2).all(|pair| pair[0] <= pair[1]), "{:?} on {:?}", estimates, position);
+                match distances.get(position) {
+                    Some(&distance) => assert!(estimates.iter().all(|&estimate| estimate <= distance), "{:?} above {} on {:?}", estimates, distance, position),
+                    None => assert!(estimates.iter().all(|&estimate| estimate > 0)),
+                }
+                // Consistent: a pour lowers no estimate by more than one
+                for previous in previousPositions {
+                    for heuristic in Heuristic::ALL {
+                        assert!(heuristic.estimate(previous, &Rules::default()) <= heuristic.estimate(position, &Rules::default()).saturating_add(1), "{:?} from {:?} to {:?}", heuristic, previous, position);
+                    }
+                }
+            }
+        }
     }
 }
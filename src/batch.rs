@@ -0,0 +1,192 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use crate::budget::*;
+use crate::puzzle::*;
+use crate::solver::*;
+use crate::stats::*;
+
+/// Lines starting with this separate the puzzles of a file holding several
+pub const DELIMITER: &str = "---";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One puzzle of a batch, named after where it was read from
+pub struct BatchEntry {
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// How solving one puzzle of a batch ended
+pub enum BatchStatus {
+    // `lowerBound` equals `moves` if the solution is proven to be the shortest
+    Solved { moves: usize, lowerBound: usize },
+    NoSolution,
+    BudgetExceeded(Limit),
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The outcome of one puzzle of a batch
+pub struct BatchReport {
+    pub name: String,
+    pub status: BatchStatus,
+    pub stats: SearchStats,
+}
+
+/// Splits the contents of a file into its puzzles, separated by lines starting with `DELIMITER`.
+/// Parts without any bottles, e.g. only a comment, are skipped.
+///
+/// # Arguments
+/// * `name` - Where the text was read from.
+/// * `text` - The contents.
+///
+/// # Returns
+/// The puzzles in file order. A puzzle is named `name` if it is the only one, otherwise `name:line`
+/// with the line it starts on. Its text starts with a blank line for every line before it, so
+/// parse errors name the line of the file.
+pub fn splitPuzzles(name: &str, text: &str) -> Vec<BatchEntry> {
+    let mut parts: Vec<(usize, String)>= vec![ (1, String::new()) ];
+    for (index, line) in text.lines().enumerate() {
+        if line.starts_with(DELIMITER) {
+            parts.push((index + 2, "\n".repeat(index + 1)));
+            continue;
+        }
+        let part= &mut parts.last_mut().unwrap().1;
+        part.push_str(line);
+        part.push('\n');
+    }
+    parts.retain(|(_, part)| part.lines().any(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#')));
+    let single= parts.len() == 1;
+    parts.into_iter()
+        .map(|(line, text)| BatchEntry { name: if single { name.to_string() } else { format!("{}:{}", name, line) }, text })
+        .collect()
+}
+
+/// Solves every puzzle of a batch with the same options.
+///
+/// # Arguments
+/// * `entries` - The puzzles.
+/// * `capacity` - The number of slots in every bottle, if known upfront.
+/// * `options` - The search settings used for every puzzle.
+/// * `jobs` - How many puzzles are solved at the same time, each on its own thread.
+///
+/// # Returns
+/// A report per puzzle, in the order of `entries`.
+pub fn solveBatch(entries: &[BatchEntry], capacity: Option<usize>, options: &SolveOptions, jobs: usize) -> Vec<BatchReport> {
+    let next= AtomicUsize::new(0);
+    let reports: Mutex<Vec<Option<BatchReport>>>= Mutex::new(vec![ None; entries.len() ]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, entries.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index= next.fetch_add(1, Ordering::Relaxed);
+                    let Some(entry)= entries.get(index) else {
+                        break;
+                    };
+                    let report= solveEntry(entry, capacity, options);
+                    reports.lock().unwrap()[index]= Some(report);
+                }
+            });
+        }
+    });
+    reports.into_inner().unwrap().into_iter().map(Option::unwrap).collect()
+}
+
+fn solveEntry(entry: &BatchEntry, capacity: Option<usize>, options: &SolveOptions) -> BatchReport {
+    let result= Puzzle::parse(&entry.text, capacity).map_err(SolveError::InvalidPuzzle).and_then(|puzzle| solve(&puzzle, options));
+    let (status, stats)= match result {
+        Ok(solution) => (BatchStatus::Solved { moves: solution.moveCount(), lowerBound: solution.lowerBound }, solution.stats),
+        Err(SolveError::NoSolution { stats }) => (BatchStatus::NoSolution, stats),
+        Err(SolveError::BudgetExceeded { limit, stats, .. }) => (BatchStatus::BudgetExceeded(limit), stats),
+        Err(SolveError::InvalidPuzzle(error)) => (BatchStatus::Invalid(error), SearchStats::default()),
+    };
+    BatchReport { name: entry.name.clone(), status, stats }
+}
+
+/// Lays out the reports as a table with a row per puzzle: whether it is solvable, the length of the
+/// solution, the positions expanded and the time taken, followed by the totals.
+///
+/// # Arguments
+/// * `reports` - The outcomes of the batch.
+/// * `elapsed` - Wall-clock time of the whole batch.
+pub fn formatSummary(reports: &[BatchReport], elapsed: Duration) -> String {
+    let header= [ "Puzzle", "Solvable", "Moves", "Expanded", "Time" ];
+    let rows: Vec<[String; 5]>= reports.iter().map(|report| {
+        let (solvable, moves)= match &report.status {
+            BatchStatus::Solved { moves, lowerBound } if lowerBound < moves => ("yes".to_string(), format!("{} (at least {})", moves, lowerBound)),
+            BatchStatus::Solved { moves, .. } => ("yes".to_string(), moves.to_string()),
+            BatchStatus::NoSolution => ("no".to_string(), "-".to_string()),
+            BatchStatus::BudgetExceeded(limit) => (format!("unknown, {} limit", limit), "-".to_string()),
+            BatchStatus::Invalid(_) => ("invalid".to_string(), "-".to_string()),
+        };
+        [ report.name.clone(), solvable, moves, report.stats.expanded.to_string(), format!("{:.3}s", report.stats.elapsed.as_secs_f64()) ]
+    }).collect();
+    let widths: Vec<usize>= (0..header.len())
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).chain(std::iter::once(header[column].len())).max().unwrap())
+        .collect();
+    let mut out= String::new();
+    let mut writeRow= |cells: &[&str]| {
+        // The name and the status are aligned left, the numbers right
+        let line: Vec<String>= cells.iter().enumerate()
+            .map(|(column, cell)| if column < 2 { format!("{:<width$}", cell, width= widths[column]) } else { format!("{:>width$}", cell, width= widths[column]) })
+            .collect();
+        writeln!(out, "{}", line.join("  ").trim_end()).unwrap();
+    };
+    writeRow(&header);
+    for row in &rows {
+        writeRow(&row.each_ref().map(String::as_str));
+    }
+    let count= |matches: fn(&BatchStatus) -> bool| reports.iter().filter(|report| matches(&report.status)).count();
+    writeln!(out, "{} puzzles: {} solved, {} unsolvable, {} stopped by a limit, {} invalid, in {:.3}s",
+             reports.len(),
+             count(|status| matches!(status, BatchStatus::Solved { .. })),
+             count(|status| matches!(status, BatchStatus::NoSolution)),
+             count(|status| matches!(status, BatchStatus::BudgetExceeded(_))),
+             count(|status| matches!(status, BatchStatus::Invalid(_))),
+             elapsed.as_secs_f64()).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn splitFile() {
+        let entries= splitPuzzles("levels.txt", "# pack 1\n---\nAB;BA;\n--- level 2\n\nABA;BAB;;\n---\n");
+        assert_eq!(entries, vec![ BatchEntry { name: "levels.txt:3".to_string(), text: "\n\nAB;BA;\n".to_string() },
+                                  BatchEntry { name: "levels.txt:5".to_string(), text: "\n\n\n\n\nABA;BAB;;\n".to_string() } ]);
+        assert_eq!(Puzzle::parse(&splitPuzzles("bad.txt", "AB;BA;\n---\nAB;BAB\n")[1].text, Some(2)).unwrap_err(),
+                   "Error: Line 3, bottle 2 \"BAB\": Each bottle must contain at most 2 characters.");
+        let entries= splitPuzzles("one.txt", "# seed 1\nAB;BA;\n");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "one.txt");
+        assert!(splitPuzzles("empty.txt", "# nothing\n").is_empty());
+    }
+
+    #[test]
+    fn solveInParallel() {
+        let text= "ABCA;BCAB;CABC;;\n---\nAB;BA\n---\nAB;BA;BB;\n---\nABA;BAB;;\n";
+        let entries= splitPuzzles("batch", text);
+        let sequential= solveBatch(&entries, None, &SolveOptions::default(), 1);
+        let statuses: Vec<&BatchStatus>= sequential.iter().map(|report| &report.status).collect();
+        assert!(matches!(statuses[0], BatchStatus::Solved { moves, lowerBound } if moves == lowerBound));
+        assert_eq!(statuses[1], &BatchStatus::NoSolution);
+        assert!(matches!(statuses[2], BatchStatus::Invalid(_)));
+        assert_eq!(statuses[3], &BatchStatus::Solved { moves: 5, lowerBound: 5 });
+        let parallel= solveBatch(&entries, None, &SolveOptions::default(), 3);
+        assert_eq!(parallel.iter().map(|report| (&report.name, &report.status)).collect::<Vec<_>>(),
+                   sequential.iter().map(|report| (&report.name, &report.status)).collect::<Vec<_>>());
+        let limited= SolveOptions { limits: SearchLimits { maxExpanded: Some(1), ..SearchLimits::default() }, ..SolveOptions::default() };
+        assert_eq!(solveBatch(&entries[..1], None, &limited, 1)[0].status, BatchStatus::BudgetExceeded(Limit::Expanded));
+        let summary= formatSummary(&sequential, Duration::from_millis(1500));
+        let lines: Vec<&str>= summary.lines().collect();
+        assert!(lines[0].starts_with("Puzzle   Solvable  Moves  Expanded"));
+        assert!(lines[2].starts_with("batch:3  no            -         1"));
+        assert_eq!(lines[5], "4 puzzles: 2 solved, 1 unsolvable, 0 stopped by a limit, 1 invalid, in 1.500s");
+    }
+}
@@ -1,7 +1,14 @@
-use std::collections::HashSet;
-use std::collections::HashMap;
 use std::rc::Rc;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 use crate::bfs::position_bfs::*;
+use crate::bottle::*;
+use crate::budget::*;
+use crate::identity::*;
+use crate::rules::*;
+use crate::stats::*;
 use crate::traits::position::*;
 
 /// Represents a move in the BFS algorithm which consists of a list of positions.
@@ -9,6 +16,16 @@ struct Move {
     positions: Vec<PositionBFS> // All unique (barring permutations) positions reachable by this move
 }
 
+/// The positions one thread found while expanding a slice of the latest move
+struct Expansion {
+    positions: Vec<PositionBFS>,
+    expanded: u64,
+    candidates: u64,
+    duplicates: u64,
+    minSyntropy: usize,
+    limit: Option<Limit>,
+}
+
 /// Handles the BFS (Breadth-First Search) process for solving a puzzle.
 pub struct BFS {
     // All reachable positions arranged per move starting from the initial position
@@ -20,10 +37,16 @@ pub struct BFS {
     // in every bottle are filled with the same color. E.g. a Bottle "BAAA" will have a syntropy
     // value of 2, because two 'A' are stacked on another 'A'. A syntropy can never decrease
     // between the moves. It can only increase or stay the same (in case we transfer some color to
-    // an empty bottle)
-    uniquePositions: HashMap<usize,HashSet<Vec<u8>>>,
+    // an empty bottle). The sets are indexed by syntropy and shared by all expanding threads
+    uniquePositions: Vec<SharedIdentitySet>,
     // Minimum syntropy of the positions in the last move
-    syntropy: usize
+    syntropy: usize,
+    // Number of threads expanding the positions of a move
+    threads: usize,
+    stats: SearchStats,
+    limits: SearchLimits,
+    rules: Rules,
+    progress: Progress
 }
 
 impl Move {
@@ -45,39 +68,116 @@ impl BFS {
     /// # Arguments
     /// * `initialPosition` - The starting point of the BFS.
     pub fn new(initialPosition: PositionBFS) -> Self {
-        let syntropy= BFS::getSyntropy(&initialPosition.getIdentity());
-        let firstHS= HashSet::from_iter(std::iter::once(initialPosition.getIdentity()));
-        let mv= Move::new(vec![initialPosition]);
-        let mut HM= HashMap::new();
-        HM.insert(syntropy, firstHS);
-        Self { moves: vec![ mv ],
-               uniquePositions: HM,
-               syntropy}
+        let mut BFS= Self { moves: Vec::new(),
+                            uniquePositions: Vec::new(),
+                            syntropy: 0,
+                            threads: 1,
+                            stats: SearchStats::default(),
+                            limits: SearchLimits::default(),
+                            rules: Rules::default(),
+                            progress: Progress::default() };
+        BFS.reset(initialPosition);
+        BFS
+    }
+
+    /// Starts the search over from `initialPosition`, sizing the collision sets for the current
+    /// number of threads
+    fn reset(&mut self, initialPosition: PositionBFS) {
+        let bottles= initialPosition.getBottles();
+        // A bottle of capacity c holds at most c - 1 slots on top of the same color
+        let maxSyntropy: usize= bottles.iter().map(|bottle| bottle.getCapacity() - 1).sum();
+        let shards= if self.threads > 1 { self.threads * 4 } else { 1 };
+        let emptySet= SharedIdentitySet::new(bottles, shards);
+        self.uniquePositions= (0..=maxSyntropy).map(|_| emptySet.newEmpty()).collect();
+        self.syntropy= BFS::getSyntropy(bottles);
+        self.uniquePositions[self.syntropy].insert(bottles);
+        self.moves= vec![ Move::new(vec![initialPosition]) ];
+    }
+
+    /// Sets how many threads expand the positions of every move. The moves, and therefore the
+    /// length of the solution, are the same for any number of threads. Must be called before the
+    /// search starts.
+    pub fn setThreads(&mut self, threads: usize) {
+        self.threads= threads.max(1);
+        let initialPosition= self.moves[0].positions[0].clone();
+        self.reset(initialPosition);
+    }
+
+    /// Caps the resources the search may use. Unlimited by default.
+    pub fn setLimits(&mut self, limits: SearchLimits) {
+        self.limits= limits;
+    }
+
+    /// Chooses which pours are made, by default with pruning
+    pub fn setRules(&mut self, rules: Rules) {
+        self.rules= rules;
+    }
+
+    /// Prints the counters of the search to stderr every `interval`, and what happens in every move.
+    /// Quiet by default.
+    pub fn setProgress(&mut self, interval: Option<Duration>) {
+        self.progress= Progress::new(interval);
     }
 
     /// Executes the BFS algorithm to find a solution.
     ///
     /// # Returns
-    /// `Option<Vec<Position>>` representing the sequence of moves to solve the puzzle if a solution is found.
-    /// `None` if no solution is possible.
-    pub fn solve(&mut self) -> Option<Vec<Rc<dyn Position>>> {
+    /// `SearchOutcome::Solved` with the sequence of positions solving the puzzle if a solution is found,
+    /// `SearchOutcome::Exhausted` if no solution is possible, or `SearchOutcome::BudgetExceeded` with the
+    /// path to the most ordered position of the deepest fully explored move if a limit was hit first.
+    pub fn solve(&mut self) -> SearchOutcome {
+        let start= Instant::now();
+        let result= self.search(start);
+        self.stats.elapsed += start.elapsed();
+        result
+    }
+
+    /// Returns the counters of the search so far
+    pub fn getStats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn search(&mut self, start: Instant) -> SearchOutcome {
         loop {
             let aMove= &self.moves[self.moves.len() - 1];
             // If we ran out of move choices, there is no solution
             if aMove.choices() < 1 {
-                return None
+                return SearchOutcome::Exhausted
             }
             // Look for a solution in a given move
             for positionIndex in 0..aMove.choices() {
                 if aMove.positions[positionIndex].isSolved() {
-                    return Some(self.buildSolutionVector(self.moves.len() - 1, positionIndex));
+                    return SearchOutcome::Solved(self.buildSolutionVector(self.moves.len() - 1, positionIndex));
                 }
             }
             // No solution was found, so make the next move
-            self.generateNewMoveChoices();
+            if let Some(limit)= self.generateNewMoveChoices(start) {
+                return SearchOutcome::BudgetExceeded(limit, self.buildBestPartialVector());
+            }
         }
     }
 
+    /// Picks the most ordered position of the last fully explored move and traces the path to it
+    fn buildBestPartialVector(&self) -> Vec<Rc<dyn Position>> {
+        let moveIndex= self.moves.len() - 1;
+        let positionIndex= self.moves[moveIndex].positions
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, position)| BFS::getSyntropy(position.getBottles()))
+            .map_or(0, |(index, _)| index);
+        self.buildSolutionVector(moveIndex, positionIndex)
+    }
+
+    /// Approximates the memory held by the search: the identities in the collision sets plus every
+    /// position kept in the moves, including the partial move being built.
+    fn getMemoryEstimate(&self, pending: usize) -> usize {
+        let bottles= self.moves[0].positions[0].getBottles().len();
+        let position= size_of::<PositionBFS>() + bottles * size_of::<Bottle>();
+        let unique: usize= self.uniquePositions.iter().map(|set| set.len()).sum();
+        let live: usize= self.moves.iter().map(|aMove| aMove.choices()).sum::<usize>() + pending;
+        unique * self.uniquePositions[0].getBytesPerPosition() + live * position
+    }
+
     /// Recursively compacts the BFS tree from a specified index to remove redundant positions and save space.
     ///
     /// # Arguments
@@ -92,8 +192,7 @@ impl BFS {
         let oldPositions= &previousMoves[index - 1].positions;
         let mut compactedOldPositions= Vec::new();
         let mut lastOldIndex: isize= -1;
-        for newPositionIndex in 0..currentPositions.len() {
-            let currentPosition= &mut currentPositions[newPositionIndex];
+        for currentPosition in currentPositions.iter_mut() {
             let previous= currentPosition.previous;
             if previous as isize > lastOldIndex {
                 compactedOldPositions.push(oldPositions[previous].clone());
@@ -107,44 +206,103 @@ impl BFS {
     }
 
     /// Generates a new move vector consisting of all possible new positions from all reachable positions 
-    /// in the latest move and updates the BFS structure accordingly.
-    fn generateNewMoveChoices(&mut self) {
-        let mut newMove= Move::new(Vec::new());
+    /// in the latest move and updates the BFS structure accordingly. The latest move is split into
+    /// consecutive slices expanded on separate threads, and their results are joined in order, so
+    /// the indices of previous positions keep growing as `compactBFS` expects.
+    ///
+    /// # Returns
+    /// The limit that was hit while expanding, if any. The partially built move is then discarded.
+    fn generateNewMoveChoices(&mut self, start: Instant) -> Option<Limit> {
         let currentPositions= &self.moves[self.moves.len() - 1].positions;
+        let expanded= AtomicU64::new(self.stats.expanded);
+        let pending= AtomicUsize::new(0);
+        let stop= AtomicBool::new(false);
+        let expansions: Vec<Expansion>= if self.threads == 1 {
+            vec![ self.expandPositions(currentPositions, 0, &expanded, &pending, &stop, start) ]
+        } else {
+            let chunkSize= currentPositions.len().div_ceil(self.threads).max(1);
+            let this= &*self;
+            thread::scope(|scope| {
+                let handles: Vec<_>= currentPositions
+                    .chunks(chunkSize)
+                    .enumerate()
+                    .map(|(chunkIndex, chunk)| {
+                        let (expanded, pending, stop)= (&expanded, &pending, &stop);
+                        scope.spawn(move || this.expandPositions(chunk, chunkIndex * chunkSize, expanded, pending, stop, start))
+                    })
+                    .collect();
+                handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+            })
+        };
+        let mut newMove= Move::new(Vec::with_capacity(expansions.iter().map(|expansion| expansion.positions.len()).sum()));
         let mut candidates= 0;
         let mut newMinSyntropy= usize::MAX;
-        for positionIndex in 0..currentPositions.len() {
-            for candidate in currentPositions[positionIndex]
-                  .getNextPossiblePositions(positionIndex) 
-            {
-                candidates += 1;
-                let candidateIdentity= candidate.getIdentity();
-                let syntropy= BFS::getSyntropy(&candidateIdentity);
-                if !self.uniquePositions
-                    .entry(syntropy)
-                    .or_insert_with(HashSet::new)
-                    .insert(candidateIdentity)
-                {
-                    continue;
-                }
-                if syntropy < newMinSyntropy {
-                    newMinSyntropy= syntropy;
-                }
-                newMove.positions.push(candidate);
-            }
+        let mut limit= None;
+        for expansion in expansions {
+            self.stats.expanded += expansion.expanded;
+            self.stats.duplicates += expansion.duplicates;
+            candidates += expansion.candidates;
+            newMinSyntropy= newMinSyntropy.min(expansion.minSyntropy);
+            limit= limit.or(expansion.limit);
+            newMove.positions.extend(expansion.positions);
+        }
+        self.stats.generated += candidates;
+        self.stats.recordPeaks(newMove.choices(), self.getMemoryEstimate(newMove.choices()));
+        if limit.is_some() {
+            return limit;
         }
-        println!("Iteration: {}, Candidates: {}, Moves: {}", self.moves.len(), candidates, newMove.positions.len());
-        if self.moves.len() % 5 == 0 {
-            println!("Pruned {} dead positions", self.compactBFS(self.moves.len() - 1));
+        if self.moves.len().is_multiple_of(5) {
+            self.compactBFS(self.moves.len() - 1);
         }
-        if newMinSyntropy > self.syntropy {
-            println!("New syntropy is {}", newMinSyntropy);
+        if newMinSyntropy > self.syntropy && newMinSyntropy != usize::MAX {
             self.syntropy= newMinSyntropy;
-            let oldHashSize= self.uniquePositions.len();
-            self.uniquePositions.retain(|&syn, _| syn >= newMinSyntropy);
-            println!("Compacting unique positions hash from {} to {}", oldHashSize, self.uniquePositions.len());
+            for syntropy in 0..newMinSyntropy {
+                self.uniquePositions[syntropy]= self.uniquePositions[syntropy].newEmpty();
+            }
         } 
         self.moves.push(newMove);
+        self.progress.report("BFS", &self.stats, start);
+        None
+    }
+
+    /// Expands a slice of the latest move, keeping the children not seen before.
+    ///
+    /// # Arguments
+    /// * `positions` - The slice to expand.
+    /// * `firstIndex` - Index of the first position of the slice within the latest move.
+    /// * `expanded` - Positions expanded so far by all threads, used to enforce the limits.
+    /// * `pending` - New positions found so far by all threads in this move.
+    /// * `stop` - Set by the first thread hitting a limit, telling the others to stop as well.
+    /// * `start` - When the search started.
+    fn expandPositions(&self, positions: &[PositionBFS], firstIndex: usize, expanded: &AtomicU64,
+                       pending: &AtomicUsize, stop: &AtomicBool, start: Instant) -> Expansion
+    {
+        let mut result= Expansion { positions: Vec::new(), expanded: 0, candidates: 0, duplicates: 0, minSyntropy: usize::MAX, limit: None };
+        for (offset, position) in positions.iter().enumerate() {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let stats= SearchStats { expanded: expanded.fetch_add(1, Ordering::Relaxed), ..self.stats };
+            if let Some(limit)= self.limits.check(&stats, start, || self.getMemoryEstimate(pending.load(Ordering::Relaxed))) {
+                stop.store(true, Ordering::Relaxed);
+                result.limit= Some(limit);
+                break;
+            }
+            result.expanded += 1;
+            let found= result.positions.len();
+            for candidate in position.getNextPossiblePositions(firstIndex + offset, &self.rules) {
+                result.candidates += 1;
+                let syntropy= BFS::getSyntropy(candidate.getBottles());
+                if !self.uniquePositions[syntropy].insert(candidate.getBottles()) {
+                    result.duplicates += 1;
+                    continue;
+                }
+                result.minSyntropy= result.minSyntropy.min(syntropy);
+                result.positions.push(candidate);
+            }
+            pending.fetch_add(result.positions.len() - found, Ordering::Relaxed);
+        }
+        result
     }
 
     /// Constructs a vector of `Position` objects representing the path from the initial position to
@@ -167,24 +325,20 @@ impl BFS {
         previousMoves
     }
 
-    /// Calculates the syntropy value for a given identity of a position.
+    /// Calculates the syntropy value for a given position.
     /// Syntropy measures the orderliness based on consecutive identical slots in each bottle.
     ///
     /// # Arguments
-    /// * `identity` - A reference to the identity vector of a position.
+    /// * `bottles` - The bottles of a position.
     ///
     /// # Returns
     /// An usize representing the syntropy value.
-    fn getSyntropy(identity: &Vec<u8>) -> usize {
-        let mut syntropy= 0;
-        for i in 0..(identity.len() / 4) {
-            for j in 0..3 {
-                if identity[i*4 + j] != (' ' as u8) && identity[i*4 + j] == identity[i*4 + j + 1] {
-                    syntropy += 1;
-                }
-            }
-        }
-        syntropy
+    pub(crate) fn getSyntropy(bottles: &[Bottle]) -> usize {
+        bottles
+            .iter()
+            .flat_map(|bottle| bottle.getContent().windows(2))
+            .filter(|pair| pair[0] != EMPTY && pair[0] == pair[1])
+            .count()
     }
 }
 
@@ -193,13 +347,30 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn parallelExpansion() {
+        let bottles: Vec<Bottle>= ["ABCD", "CDBA", "BADC", "DCAB", "    ", "    "].iter().map(|bottle| Bottle::new(bottle.as_bytes())).collect();
+        let mut serial= BFS::new(PositionBFS::new(bottles.clone(), 0));
+        let SearchOutcome::Solved(serialSolution)= serial.solve() else { panic!("The puzzle is solvable") };
+        for threads in [2, 3, 8] {
+            let mut parallel= BFS::new(PositionBFS::new(bottles.clone(), 0));
+            parallel.setThreads(threads);
+            let SearchOutcome::Solved(solution)= parallel.solve() else { panic!("The puzzle is solvable") };
+            assert_eq!(solution.len(), serialSolution.len());
+            assert!(solution.last().unwrap().getBottles().iter().all(Bottle::isSolved));
+            // Every move reaches the same positions, only the order of discovery may differ
+            assert_eq!(parallel.getStats().generated - parallel.getStats().duplicates,
+                       serial.getStats().generated - serial.getStats().duplicates);
+        }
+    }
+
     #[test]
     fn checkSyntropy() {
-        let identity= vec!['A', 'A', ' ', ' ', 'B', 'B','C', 'C']
-            .into_iter().map(|c| c as u8).collect();
-        assert_eq!(BFS::getSyntropy(&identity), 3);
-        let identity= vec!['A', 'A', ' ', ' ', 'B', 'B','C', 'C', 'C', 'C', 'A', 'A']
-            .into_iter().map(|c| c as u8).collect();
-        assert_eq!(BFS::getSyntropy(&identity), 5);
+        let bottles= vec![Bottle::newChars(&['A', 'A', ' ', ' ']), Bottle::newChars(&['B', 'B','C', 'C'])];
+        assert_eq!(BFS::getSyntropy(&bottles), 3);
+        let bottles= vec![Bottle::newChars(&['A', 'A', ' ', ' ']), Bottle::newChars(&['B', 'B','C', 'C']), Bottle::newChars(&['C', 'C', 'A', 'A'])];
+        assert_eq!(BFS::getSyntropy(&bottles), 5);
+        let bottles= vec![Bottle::newChars(&['A', 'A', 'A']), Bottle::newChars(&['B', 'B', 'B']), Bottle::newChars(&['C', ' ', ' '])];
+        assert_eq!(BFS::getSyntropy(&bottles), 4);
     }
 }
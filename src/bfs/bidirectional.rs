@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::mem::size_of;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use crate::bfs::bfs::*;
+use crate::bfs::position_bfs::*;
+use crate::bottle::*;
+use crate::budget::*;
+use crate::identity::*;
+use crate::rules::*;
+use crate::stats::*;
+use crate::traits::position::*;
+
+/// Where one side of the search found a position: the index of the move and of the position in it
+type Found= (u32, u32);
+
+/// One direction of the search
+struct Side {
+    // All unique positions arranged per move, starting from the initial or the solved position
+    moves: Vec<Vec<PositionBFS>>,
+    // Where each of these positions was found, by canonical identity
+    found: IdentityMap<Found>,
+    backward: bool, // Moves are undone pours
+}
+
+/// Both sides found the same position
+struct Meeting {
+    forward: Found,
+    backward: Found,
+}
+
+impl Side {
+    fn new(start: Vec<Bottle>, backward: bool) -> Self {
+        let mut found= IdentityMap::new(&start);
+        found.insertLower(&start, (0, 0));
+        Self { moves: vec![ vec![ PositionBFS::new(start, 0) ] ], found, backward }
+    }
+
+    /// Returns the number of positions in the latest move
+    fn frontier(&self) -> usize {
+        self.moves[self.moves.len() - 1].len()
+    }
+
+    /// Traces the bottles of every position from the start of this side to the given one
+    fn trace(&self, (moveIndex, positionIndex): Found) -> Vec<Vec<Bottle>> {
+        let mut result= Vec::new();
+        let mut index= positionIndex as usize;
+        for aMove in self.moves[..=moveIndex as usize].iter().rev() {
+            result.push(aMove[index].getBottles().clone());
+            index= aMove[index].previous;
+        }
+        result.reverse();
+        result
+    }
+}
+
+/// Bidirectional BFS: one BFS goes forward from the initial position, another goes backward with
+/// undone pours from the solved position, and the smaller frontier is expanded next. The search
+/// stops at the first position both sides have reached. Each side only needs to get about half way,
+/// so far fewer positions are visited than by a BFS from the initial position alone, and the
+/// solution still has the fewest possible moves.
+pub struct BidirectionalBFS {
+    forward: Side,
+    backward: Side,
+    stats: SearchStats,
+    limits: SearchLimits,
+    // Only applied to the forward side
+    rules: Rules,
+    progress: Progress,
+}
+
+impl BidirectionalBFS {
+    /// Constructs a new BidirectionalBFS instance searching from `initialPosition` and from the
+    /// solved position with the same bottles and colors.
+    ///
+    /// # Arguments
+    /// * `initialPosition` - The starting point of the forward search.
+    pub fn new(initialPosition: PositionBFS) -> Self {
+        let bottles= initialPosition.getBottles().clone();
+        // Without a solved position the backward side has nothing to search from
+        let solved= BidirectionalBFS::getSolvedPosition(&bottles);
+        let mut backward= Side::new(solved.clone().unwrap_or_else(|| bottles.clone()), true);
+        if solved.is_none() {
+            backward.moves[0].clear();
+        }
+        Self { forward: Side::new(bottles, false),
+               backward,
+               stats: SearchStats::default(),
+               limits: SearchLimits::default(),
+               rules: Rules::default(),
+               progress: Progress::default() }
+    }
+
+    /// Caps the resources the search may use. Unlimited by default.
+    pub fn setLimits(&mut self, limits: SearchLimits) {
+        self.limits= limits;
+    }
+
+    /// Chooses which pours are made, by default with pruning
+    pub fn setRules(&mut self, rules: Rules) {
+        self.rules= rules;
+    }
+
+    /// Prints the counters of the search to stderr every `interval`, and what happens in every move.
+    /// Quiet by default.
+    pub fn setProgress(&mut self, interval: Option<Duration>) {
+        self.progress= Progress::new(interval);
+    }
+
+    /// Returns the counters of the search so far
+    pub fn getStats(&self) -> SearchStats {
+        self.stats
+    }
+
+    /// Executes the bidirectional BFS to find a solution.
+    ///
+    /// # Returns
+    /// `SearchOutcome::Solved` with the sequence of positions solving the puzzle if a solution is found,
+    /// `SearchOutcome::Exhausted` if no solution is possible, or `SearchOutcome::BudgetExceeded` with the
+    /// path to the most ordered position of the deepest fully explored forward move if a limit was
+    /// hit first.
+    pub fn solve(&mut self) -> SearchOutcome {
+        let start= Instant::now();
+        let result= self.search(start);
+        self.stats.elapsed += start.elapsed();
+        result
+    }
+
+    fn search(&mut self, start: Instant) -> SearchOutcome {
+        if self.backward.frontier() > 0 {
+            if let Some(&backward)= self.backward.found.get(self.forward.moves[0][0].getBottles()) {
+                return SearchOutcome::Solved(self.buildSolutionVector(Meeting { forward: (0, 0), backward }));
+            }
+        }
+        loop {
+            // Once either side runs out of positions, no position can be reached from both
+            if self.forward.frontier() == 0 || self.backward.frontier() == 0 {
+                return SearchOutcome::Exhausted;
+            }
+            let result= if self.forward.frontier() <= self.backward.frontier() {
+                BidirectionalBFS::expand(&mut self.forward, &self.backward, &mut self.stats, &self.limits, &self.rules, start)
+            } else {
+                BidirectionalBFS::expand(&mut self.backward, &self.forward, &mut self.stats, &self.limits, &self.rules, start)
+            };
+            self.stats.recordPeaks(self.forward.frontier() + self.backward.frontier(), BidirectionalBFS::getMemoryEstimate(&self.forward, &self.backward, 0));
+            self.progress.report("Bidirectional BFS", &self.stats, start);
+            match result {
+                Ok(Some(meeting)) => return SearchOutcome::Solved(self.buildSolutionVector(meeting)),
+                Ok(None) => {},
+                Err(limit) => return SearchOutcome::BudgetExceeded(limit, self.buildBestPartialVector()),
+            }
+        }
+    }
+
+    /// Expands the positions of the latest move of one side until one of them is found by the other
+    /// side as well. The sides have not met before, so the shortest solution is longer than both
+    /// their depths together, and the first meeting is on a shortest solution.
+    ///
+    /// # Returns
+    /// Where the sides met, if they did, or the limit that was hit while expanding. The partially
+    /// built move is then discarded.
+    fn expand(side: &mut Side, other: &Side, stats: &mut SearchStats, limits: &SearchLimits, rules: &Rules, start: Instant) -> Result<Option<Meeting>, Limit> {
+        let moveIndex= side.moves.len() as u32;
+        let mut newMove: Vec<PositionBFS>= Vec::new();
+        let mut meeting= None;
+        let mut candidates= 0;
+        'expansion: for (index, position) in side.moves[side.moves.len() - 1].iter().enumerate() {
+            let pending= newMove.len();
+            if let Some(limit)= limits.check(stats, start, || BidirectionalBFS::getMemoryEstimate(side, other, pending)) {
+                return Err(limit);
+            }
+            stats.expanded += 1;
+            let children= if side.backward {
+                position.getPreviousPossiblePositions(index, rules)
+            } else {
+                position.getNextPossiblePositions(index, rules)
+            };
+            for child in children {
+                candidates += 1;
+                let reached= (moveIndex, newMove.len() as u32);
+                if !side.found.insertLower(child.getBottles(), reached) {
+                    stats.duplicates += 1;
+                    continue;
+                }
+                let found= other.found.get(child.getBottles()).copied();
+                newMove.push(child);
+                if let Some(found)= found {
+                    meeting= Some(if side.backward { Meeting { forward: found, backward: reached } }
+                                  else { Meeting { forward: reached, backward: found } });
+                    break 'expansion;
+                }
+            }
+        }
+        stats.generated += candidates;
+        side.moves.push(newMove);
+        Ok(meeting)
+    }
+
+    /// Approximates the memory held by the search: the identities seen by both sides plus every
+    /// position kept in their moves, including the partial move being built.
+    fn getMemoryEstimate(side: &Side, other: &Side, pending: usize) -> usize {
+        let bottles= side.moves[0].first().or(other.moves[0].first()).map_or(0, |position| position.getBottles().len());
+        let position= size_of::<PositionBFS>() + bottles * size_of::<Bottle>();
+        let live: usize= [side, other].iter().flat_map(|side| side.moves.iter()).map(Vec::len).sum::<usize>() + pending;
+        (side.found.len() + other.found.len()) * side.found.getBytesPerPosition() + live * position
+    }
+
+    /// Joins the paths of both sides at the position where they met. The positions of the backward
+    /// side have their bottles in another order, so they are rearranged like the forward ones.
+    fn buildSolutionVector(&self, meeting: Meeting) -> Vec<Rc<dyn Position>> {
+        let forwardPath= self.forward.trace(meeting.forward);
+        let mut backwardPath= self.backward.trace(meeting.backward);
+        backwardPath.reverse();
+        let met= &forwardPath[forwardPath.len() - 1];
+        // For every bottle of the backward side, the index of an equal bottle of the forward side
+        let mut unused: Vec<Option<&Bottle>>= met.iter().map(Some).collect();
+        let order: Vec<usize>= backwardPath[0].iter()
+            .map(|bottle| {
+                let index= unused.iter().position(|candidate| *candidate == Some(bottle)).expect("Both sides must have met at the same position");
+                unused[index]= None;
+                index
+            })
+            .collect();
+        let rearranged= backwardPath.iter().skip(1).map(|bottles| {
+            let mut result= bottles.clone();
+            for (from, &to) in order.iter().enumerate() {
+                result[to]= bottles[from].clone();
+            }
+            result
+        });
+        forwardPath.iter().cloned().chain(rearranged)
+            .map(|bottles| Rc::new(PositionBFS::new(bottles, 0)) as Rc<dyn Position>)
+            .collect()
+    }
+
+    /// Picks the most ordered position of the last fully explored forward move and traces the path to it
+    fn buildBestPartialVector(&self) -> Vec<Rc<dyn Position>> {
+        let moveIndex= self.forward.moves.len() - 1;
+        let positionIndex= self.forward.moves[moveIndex]
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, position)| BFS::getSyntropy(position.getBottles()))
+            .map_or(0, |(index, _)| index);
+        self.forward.trace((moveIndex as u32, positionIndex as u32))
+            .into_iter()
+            .map(|bottles| Rc::new(PositionBFS::new(bottles, 0)) as Rc<dyn Position>)
+            .collect()
+    }
+
+    /// Builds the solved position with the same bottles and colors as `bottles`: every color fills
+    /// a bottle of its size and the other bottles are empty. All solved positions are permutations
+    /// of this one.
+    ///
+    /// # Returns
+    /// `None` if some color does not fill a bottle exactly.
+    fn getSolvedPosition(bottles: &[Bottle]) -> Option<Vec<Bottle>> {
+        let mut counts= BTreeMap::new();
+        for &slot in bottles.iter().flat_map(Bottle::getContent) {
+            if slot != EMPTY {
+                *counts.entry(slot).or_insert(0) += 1;
+            }
+        }
+        let mut solved: Vec<Bottle>= bottles.iter().map(|bottle| Bottle::new(&vec![EMPTY; bottle.getCapacity()])).collect();
+        for (color, count) in counts {
+            let bottle= solved.iter_mut().find(|bottle| bottle.isEmpty() && bottle.getCapacity() == count)?;
+            *bottle= Bottle::new(&vec![color; count]);
+        }
+        Some(solved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::pour::*;
+
+    fn solve(contents: &[&str]) -> SearchOutcome {
+        let bottles: Vec<Bottle>= contents.iter().map(|bottle| Bottle::new(bottle.as_bytes())).collect();
+        BidirectionalBFS::new(PositionBFS::new(bottles, 0)).solve()
+    }
+
+    #[test]
+    fn sameLengthAsBFS() {
+        for contents in [&["ABCD", "CDBA", "BADC", "DCAB", "    ", "    "][..], &["ABCA", "BCAB", "CABC", "    ", "    "],
+                         &["AB", "BA", "  "], &["AA", "BB", "  "]] {
+            let bottles: Vec<Bottle>= contents.iter().map(|bottle| Bottle::new(bottle.as_bytes())).collect();
+            let SearchOutcome::Solved(expected)= BFS::new(PositionBFS::new(bottles.clone(), 0)).solve() else { panic!("The puzzle is solvable") };
+            let SearchOutcome::Solved(solution)= solve(contents) else { panic!("The puzzle is solvable") };
+            assert_eq!(solution.len(), expected.len());
+            assert_eq!(solution[0].getBottles(), &bottles);
+            assert!(solution.last().unwrap().getBottles().iter().all(Bottle::isSolved));
+            // Every step is a legal pour between the bottles as numbered in the input
+            for pair in solution.windows(2) {
+                assert!(Pour::between(pair[0].getBottles(), pair[1].getBottles()).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn unsolvable() {
+        assert!(matches!(solve(&["AB", "BA"]), SearchOutcome::Exhausted));
+        assert!(matches!(solve(&["AB", "B ", "  "]), SearchOutcome::Exhausted));
+    }
+
+    #[test]
+    fn solvedPosition() {
+        let bottles= vec![Bottle::new(b"AB  "), Bottle::new(b"BAAA"), Bottle::new(b"BB"), Bottle::new(b"    ")];
+        let solved= BidirectionalBFS::getSolvedPosition(&bottles).unwrap();
+        assert_eq!(solved, vec![Bottle::new(b"AAAA"), Bottle::new(b"BBBB"), Bottle::new(b"  "), Bottle::new(b"    ")]);
+        assert!(BidirectionalBFS::getSolvedPosition(&[Bottle::new(b"AB"), Bottle::new(b"A ")]).is_none());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod position_bfs;
 pub mod bfs;
+pub mod bidirectional;
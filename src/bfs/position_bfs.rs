@@ -1,8 +1,8 @@
-use std::collections::HashMap;
 use std::fmt::Write;
 use std::fmt;
 use std::rc::Rc;
 use crate::bottle::*;
+use crate::rules::*;
 use crate::traits::position::*;
 
 #[derive(Debug, Clone)]
@@ -19,51 +19,20 @@ impl PositionBFS {
         Self { bottles, previous }
     }
 
-    /// Unique identifier based on the sorted contents of the bottles.
-    /// Allows to identify identical positions with all bottle permutations
-    pub fn getIdentity(&self) -> Vec<u8> {
-        let mut cloned_bottles = self.bottles.clone();
-        cloned_bottles.sort();
-        cloned_bottles
-            .into_iter()
-            .flat_map(|bottle| bottle.content.into_iter())
-            .collect()
-    }
-
     #[inline]
     /// # Returns
     /// `true` if all bottles are solved, otherwise `false`.
     pub fn isSolved(&self) -> bool {
-        self.bottles.iter().all(|bottle| bottle.isSolved())
+        isSolvedPosition(&self.bottles)
     }
 
-    /// Validates that all characters (colors) in the bottles appear exactly 4 times,
-    /// ensuring the position meets game rules.
+    /// Validates that every character (color) in the bottles fills a bottle of one of the sizes
+    /// exactly, ensuring the position meets game rules. See `checkColorCounts`.
     ///
     /// # Returns
     /// `Ok(())` if the validation passes, otherwise `Err` with a message detailing inconsistencies.
     pub fn isValid(&self) -> Result<(), String> {
-        let mut char_count = HashMap::new();
-        // Iterate over each array and then each character in the array
-        for bottle in &self.bottles {
-            for &ch in bottle.content.iter() {
-                if ch != ' ' as u8 {
-                    *char_count.entry(ch).or_insert(0) += 1;
-                }
-            }
-        }
-
-        if char_count.values().all(|&count| count == 4) {
-            return Ok(());
-        }
-        let mut error= String::new();
-        error.push_str("Error, the position is invalid! Please count the characters: \n");
-        for (&character, &count) in char_count.iter() {
-            if count != 4 {
-                write!(error, "Character: '{}', Count: {}\n", character, count).unwrap();
-            }
-        }
-        Err(error)
+        checkColorCounts(&self.bottles)
     }
 
     /// Generates all valid next positions reachable in one move by attempting to transfer contents
@@ -71,25 +40,70 @@ impl PositionBFS {
     ///
     /// # Arguments
     /// * `myIndex` - The current position's index, used as the `previous` index for new positions.
+    /// * `rules` - Which pours are made.
     ///
     /// # Returns
     /// A vector of `Position` instances representing all possible next states.
-    pub fn getNextPossiblePositions(&self, myIndex: usize) -> Vec<PositionBFS> {
+    pub fn getNextPossiblePositions(&self, myIndex: usize, rules: &Rules) -> Vec<PositionBFS> {
         let mut result: Vec<PositionBFS>= Vec::new();
         let mut newBottles= self.bottles.clone();
         let bottleNum= self.bottles.len();
+        let locked= self.bottles.iter().any(Bottle::isLocked);
         for i in 0..bottleNum {
             for j in 0..bottleNum {
-                if i < j {
+                let poured= if i < j {
                     let (left, right) = newBottles.split_at_mut(j);
-                    if left[i].fillFrom(&mut right[0]) {
-                        result.push(PositionBFS::new(newBottles, myIndex));
-                        newBottles= self.bottles.clone();
-                    }
+                    rules.pour(&mut left[i], &mut right[0])
                 }
                 else if i > j {
                     let (left, right) = newBottles.split_at_mut(i);
-                    if right[0].fillFrom(&mut left[j]) {
+                    rules.pour(&mut right[0], &mut left[j])
+                }
+                else {
+                    false
+                };
+                if poured {
+                    if !rules.isRedundant(&self.bottles, j, i) {
+                        if locked {
+                            passMove(&mut newBottles);
+                        }
+                        result.push(PositionBFS::new(newBottles, myIndex));
+                    }
+                    newBottles= self.bottles.clone();
+                }
+            }
+        }
+        result
+    }
+
+    /// Generates all positions this position is reachable from in one move, by undoing every pour
+    /// of every amount into each bottle.
+    ///
+    /// # Arguments
+    /// * `myIndex` - The current position's index, used as the `previous` index for new positions.
+    /// * `rules` - How much a pour moves. Pruning does not apply, and neither do locks, as a position
+    ///   does not tell how its locks were a move earlier.
+    ///
+    /// # Returns
+    /// A vector of `Position` instances from which a single pour leads to this position.
+    pub fn getPreviousPossiblePositions(&self, myIndex: usize, rules: &Rules) -> Vec<PositionBFS> {
+        let mut result: Vec<PositionBFS>= Vec::new();
+        let mut newBottles= self.bottles.clone();
+        let bottleNum= self.bottles.len();
+        for i in 0..bottleNum {
+            for j in 0..bottleNum {
+                if i == j {
+                    continue;
+                }
+                for amount in 1..=self.bottles[i].getCapacity() {
+                    let (destination, source)= if i < j {
+                        let (left, right)= newBottles.split_at_mut(j);
+                        (&mut left[i], &mut right[0])
+                    } else {
+                        let (left, right)= newBottles.split_at_mut(i);
+                        (&mut right[0], &mut left[j])
+                    };
+                    if rules.unpour(destination, source, amount) {
                         result.push(PositionBFS::new(newBottles, myIndex));
                         newBottles= self.bottles.clone();
                     }
@@ -104,7 +118,7 @@ impl PositionBFS {
 impl Position for PositionBFS {
 
     fn getBottles(&self) -> &Vec<Bottle> {
-        return &self.bottles;
+        &self.bottles
     }
 
     /// Creates a string representation of the position,
@@ -123,14 +137,20 @@ impl Position for PositionBFS {
                 return Err("Two positions have different length!".to_string());
             }
         }
-        for i in (0..4).rev() {
+        let height= self.bottles.iter().map(|bottle| bottle.getCapacity()).max().unwrap_or(0);
+        for i in (0..height).rev() {
             for j in 0..length {
-                if j > 0 && j % ((length + 2) / 3) == 0 {
+                if j > 0 && j % length.div_ceil(3) == 0 {
                     out.push_str("  ");
                 }
                 let ourBottle= &self.bottles[j];
+                // Shorter bottles have no slots up here
+                if i >= ourBottle.getCapacity() {
+                    out.push_str("   ");
+                    continue;
+                }
                 // if previous exists and our bottles aren't equal, make our bottle bold
-                if let Some(_) = possiblePrevious.as_ref().filter(|p| ourBottle != &p.getBottles()[j]) {
+                if possiblePrevious.as_ref().is_some_and(|p| ourBottle.getContent() != p.getBottles()[j].getContent()) {
                     write!(out, "❚{}❚", ourBottle.content[i] as char).unwrap();
                 } else {
                     write!(out, "|{}|", ourBottle.content[i] as char).unwrap();
@@ -146,9 +166,14 @@ impl Position for PositionBFS {
 
 impl fmt::Display for PositionBFS {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for i in (0..4).rev() {
+        let height= self.bottles.iter().map(|bottle| bottle.getCapacity()).max().unwrap_or(0);
+        for i in (0..height).rev() {
             for bottle in &self.bottles {
-                write!(f, "|{}|", bottle.content[i] as char)?;
+                if i < bottle.getCapacity() {
+                    write!(f, "|{}|", bottle.content[i] as char)?;
+                } else {
+                    write!(f, "   ")?;
+                }
             }
             writeln!(f)?;
         }
@@ -163,14 +188,26 @@ mod tests {
     use super::*;
     use std::collections::HashSet;
 
+    impl PositionBFS {
+        /// The sorted contents of the bottles, identical for all bottle permutations
+        fn getIdentity(&self) -> Vec<u8> {
+            let mut cloned_bottles = self.bottles.clone();
+            cloned_bottles.sort();
+            cloned_bottles
+                .into_iter()
+                .flat_map(|bottle| bottle.content.into_iter().take(bottle.getCapacity()))
+                .collect()
+        }
+    }
+
     #[test]
     fn checkNextPositions() {
-        let bottle1= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let bottle2= Bottle::newChars([ 'B', 'B', 'A', ' ']);
-        let bottle4= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle5= Bottle::newChars([ 'B', 'B', 'B', ' ']);
+        let bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle5= Bottle::newChars(&[ 'B', 'B', 'B', ' ']);
         let pos1= PositionBFS::new(vec![bottle1, bottle2, bottle4, bottle5], 0);
-        let newPositions= pos1.getNextPossiblePositions(0);
+        let newPositions= pos1.getNextPossiblePositions(0, &Rules { pruning: false, ..Rules::default() });
         assert_eq!(newPositions.len(), 4);
         let expectedIdentities: HashSet<Vec<u8>> = Vec::from_iter([
             vec!['A', 'A', 'A', ' ', 'B', 'B', ' ', ' ', 'B', 'B', ' ', ' ', 'B', 'B', 'B', ' '],
@@ -183,21 +220,55 @@ mod tests {
                 .map(|c| c as u8) // Safe cast, as all chars are guaranteed to be ASCII
                 .collect::<Vec<u8>>() // Collect bytes into Vec<u8>
             }).collect();
-        newPositions.iter().for_each(|position| { /* println!("{:?}", position.identity); */ assert_eq!(expectedIdentities.contains(&position.getIdentity()), true) });
+        newPositions.iter().for_each(|position| { assert!(expectedIdentities.contains(&position.getIdentity())) });
     }
 
     #[test]
     fn checkNumNextPositions() {
-        let bottle1= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let bottle2= Bottle::newChars([ 'B', 'B', 'A', ' ']);
-        let bottle3= Bottle::newChars([ 'A', 'A', 'A', ' ']);
-        let bottle4= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle5= Bottle::newChars([ 'B', 'B', 'B', ' ']);
+        let bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle5= Bottle::newChars(&[ 'B', 'B', 'B', ' ']);
         let pos1= PositionBFS::new(vec![bottle1, bottle2, bottle3, bottle4, bottle5], 0);
-        let newPositions= pos1.getNextPossiblePositions(0);
+        let newPositions= pos1.getNextPossiblePositions(0, &Rules { pruning: false, ..Rules::default() });
         assert_eq!(newPositions.len(), 8);
 //        for pos in &newPositions {
 //            println!("{:?}", pos);
 //        }
     }
+
+    #[test]
+    fn checkWholeRunPositions() {
+        let bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle5= Bottle::newChars(&[ 'B', 'B', 'B', ' ']);
+        let pos1= PositionBFS::new(vec![bottle1, bottle2, bottle3, bottle4, bottle5], 0);
+        let rules= Rules { pruning: false, pour: PourRule::WholeRun };
+        let newPositions= pos1.getNextPossiblePositions(0, &rules);
+        // Only the single A of the second bottle fits entirely onto the other A's
+        assert_eq!(newPositions.len(), 2);
+    }
+
+    #[test]
+    fn checkPreviousPositions() {
+        let bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle5= Bottle::newChars(&[ 'B', 'B', 'B', ' ']);
+        let pos1= PositionBFS::new(vec![bottle1, bottle2, bottle4, bottle5], 0);
+        let previousPositions= pos1.getPreviousPossiblePositions(0, &Rules::default());
+        // Undone pours must leave the same color below or nothing, and can't end on the same color
+        // unless the bottle poured into is full: 3 each from the first and the last two bottles
+        assert_eq!(previousPositions.len(), 9);
+        for previous in previousPositions {
+            assert!(previous.getNextPossiblePositions(0, &Rules { pruning: false, ..Rules::default() }).iter().any(|next| next.getBottles() == pos1.getBottles()));
+        }
+        // Every forward move can be undone
+        for next in pos1.getNextPossiblePositions(0, &Rules { pruning: false, ..Rules::default() }) {
+            assert!(next.getPreviousPossiblePositions(0, &Rules::default()).iter().any(|previous| previous.getBottles() == pos1.getBottles()));
+        }
+    }
 }
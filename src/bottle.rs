@@ -1,50 +1,154 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fmt::Write;
+
+/// The tallest bottle the solver can represent
+pub const MAX_CAPACITY: usize = 8;
+
+/// The byte representing an empty slot in a bottle
+pub const EMPTY: u8 = b' ';
+
+/// The byte representing a hidden slot whose color is revealed once everything above it is poured out
+pub const UNKNOWN: u8 = b'?';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+/// Whether a bottle can take part in a pour. A locked bottle can neither be poured from nor into
+/// until its condition is met, after which it stays open.
+pub enum Lock {
+    #[default]
+    Open,
+    Moves(u8), // Opens after this many more moves
+    Color(u8), // Opens once another bottle is completed with this color
+}
+
+impl fmt::Display for Lock {
+    /// Formats the lock the way the puzzle input writes it, e.g. "@3" or "@R", and nothing if open
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lock::Open => f.pad(""),
+            Lock::Moves(moves) => f.pad(&format!("@{}", moves)),
+            Lock::Color(color) => f.pad(&format!("@{}", *color as char)),
+        }
+    }
+}
+
 /// Representation of a bottle in a liquid sort game
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Bottle {
+    // How many slots this bottle has. Slots at and above `capacity` are always empty
+    capacity: u8,
     // ascii letters represent colors. ' ' (space) represents empty space
-    pub content: [u8; 4]
+    pub content: [u8; MAX_CAPACITY],
+    pub lock: Lock,
 }
 
 impl Bottle {
-    pub fn new(content: [u8; 4]) -> Self {
-        Self { content }
+    /// Creates a bottle with as many slots as `content` has bytes, listed from the bottom up.
+    ///
+    /// # Panics
+    /// If `content` is empty or longer than `MAX_CAPACITY`.
+    pub fn new(content: &[u8]) -> Self {
+        assert!(!content.is_empty() && content.len() <= MAX_CAPACITY, "Unsupported bottle capacity {}", content.len());
+        let mut slots= [EMPTY; MAX_CAPACITY];
+        slots[..content.len()].copy_from_slice(content);
+        Self { capacity: content.len() as u8, content: slots, lock: Lock::Open }
+    }
+
+    /// Returns this bottle locked until `lock` opens
+    pub fn withLock(mut self, lock: Lock) -> Self {
+        self.lock= lock;
+        self
+    }
+
+    #[inline]
+    /// Checks if the bottle can neither be poured from nor into yet
+    pub fn isLocked(&self) -> bool {
+        self.lock != Lock::Open
+    }
+
+    #[inline]
+    /// Checks if the bottle is full of a single known color
+    pub fn isCompleted(&self) -> bool {
+        !self.isEmpty() && self.isSolved()
+    }
+
+    #[inline]
+    /// Returns the number of slots in this bottle
+    pub fn getCapacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    #[inline]
+    /// Returns the slots of this bottle from the bottom up
+    pub fn getContent(&self) -> &[u8] {
+        &self.content[..self.getCapacity()]
     }
 
     #[inline]
     pub fn isEmpty(&self) -> bool {
-        self.content[0] == ' ' as u8
+        self.content[0] == EMPTY
     }
 
     #[inline]
     /// Checks if the bottle is in a "solved" state.
-    /// A bottle is considered solved if all its content slots contain the same non-space character, 
+    /// A bottle is considered solved if all its content slots contain the same non-space character,
     /// or if the first slot is a space, implying the bottle is empty or correctly arranged.
+    /// Hidden slots are never solved, as their color is not known yet.
     pub fn isSolved(&self) -> bool {
         if self.isEmpty() {
             return true;
         }
         let color= self.content[0];
-        self.content[1] == color && self.content[2] == color && self.content[3] == color
+        color != UNKNOWN && self.getContent().iter().all(|&slot| slot == color)
+    }
+
+    #[inline]
+    /// Returns the number of filled slots
+    pub fn getLevel(&self) -> usize {
+        if self.isEmpty() { 0 } else { self.getTopIndex() + 1 }
+    }
+
+    #[inline]
+    /// Checks if the bottle holds a single known color, whether it is full or not
+    pub fn isMonochrome(&self) -> bool {
+        let color= self.content[0];
+        color != EMPTY && color != UNKNOWN && self.getContent().iter().all(|&slot| slot == color || slot == EMPTY)
+    }
+
+    #[inline]
+    /// Checks if the topmost liquid of the bottle is a hidden slot, i.e. a pour has just uncovered it
+    pub fn isTopHidden(&self) -> bool {
+        self.content[self.getTopIndex()] == UNKNOWN
     }
 
     #[inline]
     /// Return the top empty slot index
     pub fn getTopIndex(&self) -> usize {
-        let mut i: usize= 3;
-        while self.content[i] == (' ' as u8) && i > 0 {
+        let mut i: usize= self.getCapacity() - 1;
+        while self.content[i] == EMPTY && i > 0 {
             i-= 1;
         }
         i
     }
 
+    /// Returns how many slots the top color fills in a row, 0 for an empty bottle
+    pub fn getTopTowerHeight(&self) -> usize {
+        if self.isEmpty() {
+            return 0;
+        }
+        let topIndex= self.getTopIndex();
+        let color= self.content[topIndex];
+        self.content[..=topIndex].iter().rev().take_while(|&&slot| slot == color).count()
+    }
+
     /// Return how many towers of the same color are stacked in this bottle
     pub fn getColorTowers(&self) -> usize {
         if self.isEmpty() {
             return 0;
         }
         let mut towers= 1;
-        for i in 1..4 {
-            if self.content[i] != (' ' as u8) && self.content[i] != self.content[i-1] {
+        for i in 1..self.getCapacity() {
+            if self.content[i] != EMPTY && self.content[i] != self.content[i-1] {
                 towers += 1;
             }
         }
@@ -62,32 +166,202 @@ impl Bottle {
     /// `true` if any content was transferred, `false` otherwise.
     pub fn fillFrom(&mut self, other: &mut Bottle) -> bool {
         let mut otherTopIndex: isize= other.getTopIndex() as isize;
-        if other.content[otherTopIndex as usize] == ' ' as u8 {
+        if other.content[otherTopIndex as usize] == EMPTY {
             return false;
         }
+        let lastIndex= self.getCapacity() - 1;
         let mut ourTopIndex= self.getTopIndex();
         let mut mutated= false;
-        let ourTopColor= if self.content[0] == ' ' as u8 {
+        let ourTopColor= if self.content[0] == EMPTY {
             self.content[0]= other.content[otherTopIndex as usize];
-            other.content[otherTopIndex as usize]= ' ' as u8;
+            other.content[otherTopIndex as usize]= EMPTY;
             otherTopIndex-= 1;
             mutated= true;
             self.content[0]
-        } else { 
-            self.content[ourTopIndex] 
+        } else {
+            self.content[ourTopIndex]
         };
-        while ourTopIndex < 3 
+        while ourTopIndex < lastIndex
             && otherTopIndex >= 0
             && other.content[otherTopIndex as usize] == ourTopColor
         {
             ourTopIndex+= 1;
             self.content[ourTopIndex]= other.content[otherTopIndex as usize];
-            other.content[otherTopIndex as usize]= ' ' as u8;
+            other.content[otherTopIndex as usize]= EMPTY;
             otherTopIndex-= 1;
             mutated= true;
         }
         mutated
     }
+
+    /// Like `fillFrom`, but only if the whole run of the top color of `other` fits into this bottle, as
+    /// in games that never pour partially.
+    ///
+    /// # Returns
+    /// `true` if the run was transferred, `false` otherwise.
+    pub fn fillWholeFrom(&mut self, other: &mut Bottle) -> bool {
+        if other.isEmpty() || self.getCapacity() - self.getLevel() < other.getTopTowerHeight() {
+            return false;
+        }
+        self.fillFrom(other)
+    }
+
+    /// Transfers only the topmost slot of `other` into this bottle, as in games pouring a single unit
+    /// per move. It must land in an empty bottle or on the same color.
+    ///
+    /// # Returns
+    /// `true` if the slot was transferred, `false` otherwise.
+    pub fn fillOneFrom(&mut self, other: &mut Bottle) -> bool {
+        if other.isEmpty() {
+            return false;
+        }
+        let otherTopIndex= other.getTopIndex();
+        let color= other.content[otherTopIndex];
+        let level= self.getLevel();
+        if level == self.getCapacity() || (level > 0 && self.content[level - 1] != color) {
+            return false;
+        }
+        self.content[level]= color;
+        other.content[otherTopIndex]= EMPTY;
+        true
+    }
+
+    /// Undoes a single unit pour: moves the top slot of this bottle back onto `other`, provided that
+    /// `fillOneFrom` could pour it from there into this bottle.
+    ///
+    /// # Returns
+    /// `true` if the slot was moved back, `false` (leaving both bottles untouched) otherwise.
+    pub fn unfillOneInto(&mut self, other: &mut Bottle) -> bool {
+        if self.isEmpty() || other.getLevel() == other.getCapacity() {
+            return false;
+        }
+        let ourTopIndex= self.getTopIndex();
+        let color= self.content[ourTopIndex];
+        // The slot must have landed on the same color, or in an empty bottle
+        if ourTopIndex > 0 && self.content[ourTopIndex - 1] != color {
+            return false;
+        }
+        let otherCount= other.getLevel();
+        other.content[otherCount]= color;
+        self.content[ourTopIndex]= EMPTY;
+        true
+    }
+
+    /// Undoes a pour: moves the top `amount` slots of this bottle back onto `other`, provided that
+    /// pouring them from `other` into this bottle with `fillFrom` would move exactly these slots.
+    ///
+    /// # Parameters
+    /// * `other` - The bottle the slots were poured from.
+    /// * `amount` - The number of slots the pour moved.
+    ///
+    /// # Returns
+    /// `true` if the bottles now hold a position the current one is one pour away from, `false`
+    /// (leaving both bottles untouched) if no pour of `amount` slots from `other` leads here.
+    pub fn unfillInto(&mut self, other: &mut Bottle, amount: usize) -> bool {
+        if self.isEmpty() || amount == 0 {
+            return false;
+        }
+        let ourTopIndex= self.getTopIndex();
+        let color= self.content[ourTopIndex];
+        let towerHeight= self.content[..=ourTopIndex].iter().rev().take_while(|&&slot| slot == color).count();
+        // Below the poured slots there must be the same color, or nothing
+        if amount > towerHeight || (amount == towerHeight && towerHeight <= ourTopIndex) {
+            return false;
+        }
+        let otherCount= other.getLevel();
+        if otherCount + amount > other.getCapacity() {
+            return false;
+        }
+        // Had the same color been left in `other`, the pour must have stopped because this bottle was full
+        if otherCount > 0 && other.content[otherCount - 1] == color && ourTopIndex + 1 < self.getCapacity() {
+            return false;
+        }
+        for i in 0..amount {
+            other.content[otherCount + i]= color;
+            self.content[ourTopIndex - i]= EMPTY;
+        }
+        true
+    }
+}
+
+/// Checks if every bottle is solved and no color is split over several bottles. Splitting is only
+/// possible with bottles of different sizes, e.g. a color of 6 units in two bottles of 3.
+pub fn isSolvedPosition(bottles: &[Bottle]) -> bool {
+    if !bottles.iter().all(Bottle::isSolved) {
+        return false;
+    }
+    let mut seen= [false; 256];
+    bottles.iter()
+        .filter(|bottle| !bottle.isEmpty())
+        .all(|bottle| !std::mem::replace(&mut seen[bottle.content[0] as usize], true))
+}
+
+/// Opens the locks whose condition is met: a color lock once another bottle is completed with its
+/// color. Called on the initial position and after every move, see `passMove`.
+pub fn openLocks(bottles: &mut [Bottle]) {
+    for i in 0..bottles.len() {
+        if let Lock::Color(color)= bottles[i].lock {
+            let completed= bottles.iter()
+                .enumerate()
+                .any(|(j, bottle)| j != i && bottle.isCompleted() && bottle.content[0] == color);
+            if completed {
+                bottles[i].lock= Lock::Open;
+            }
+        }
+    }
+}
+
+/// Updates the locks of the position a move has just led to: every move lock counts down by one
+/// move, and color locks open once their color is completed.
+pub fn passMove(bottles: &mut [Bottle]) {
+    for bottle in bottles.iter_mut() {
+        match bottle.lock {
+            Lock::Moves(1) => bottle.lock= Lock::Open,
+            Lock::Moves(moves) => bottle.lock= Lock::Moves(moves - 1),
+            _ => {},
+        }
+    }
+    openLocks(bottles);
+}
+
+/// Validates that every character (color) fills a bottle exactly, and that there are enough bottles
+/// of each size for the colors filling them. Bottles of different sizes are not interchangeable.
+///
+/// # Returns
+/// `Ok(())` if the validation passes, otherwise `Err` with a message detailing inconsistencies.
+pub fn checkColorCounts(bottles: &[Bottle]) -> Result<(), String> {
+    // How many bottles there are of each size
+    let mut sizes= BTreeMap::new();
+    for bottle in bottles {
+        *sizes.entry(bottle.getCapacity()).or_insert(0) += 1;
+    }
+    let mut char_count = HashMap::new();
+    // Iterate over each array and then each character in the array
+    for bottle in bottles {
+        for &ch in bottle.getContent() {
+            if ch != EMPTY {
+                *char_count.entry(ch).or_insert(0) += 1;
+            }
+        }
+    }
+    if char_count.values().any(|count| !sizes.contains_key(count)) {
+        let names: Vec<String>= sizes.keys().map(usize::to_string).collect();
+        let mut error= String::new();
+        writeln!(error, "Error, the position is invalid! Each color must fill a bottle of {} exactly. Please count the characters: ", names.join(" or ")).unwrap();
+        for (&character, &count) in char_count.iter() {
+            if !sizes.contains_key(&count) {
+                writeln!(error, "Character: '{}', Count: {}", character as char, count).unwrap();
+            }
+        }
+        return Err(error);
+    }
+    for (&size, &available) in &sizes {
+        let colors= char_count.values().filter(|&&count| count == size).count();
+        if colors > available {
+            return Err(format!("Error, the position is invalid! {} colors must each fill a bottle of {}, but the puzzle has {} of that size", colors, size, available));
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -96,77 +370,246 @@ mod tests {
     use super::*;
 
     impl Bottle {
-        pub fn newChars(content: [char; 4]) -> Self {
-            let byteArray= [ content[0] as u8, content[1] as u8, content[2] as u8, content[3] as u8 ];
-            Self { content: byteArray }
+        pub fn newChars(content: &[char]) -> Self {
+            let bytes: Vec<u8>= content.iter().map(|&c| c as u8).collect();
+            Bottle::new(&bytes)
         }
     }
 
     #[test]
     fn fillCopySingleColor() {
-        let mut bottle1= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let mut bottle2= Bottle::newChars([ 'B', 'B', 'A', ' ']);
-        let bottle3= Bottle::newChars([ 'A', 'A', 'A', ' ']);
-        let bottle4= Bottle::newChars([ 'B', 'B', ' ', ' ']);
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let mut bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
         let mutated= bottle1.fillFrom(&mut bottle2);
-        assert!(mutated == true);
+        assert!(mutated);
         assert_eq!(bottle1, bottle3);
         assert_eq!(bottle2, bottle4);
     }
 
     #[test]
     fn fillOtherEmpty() {
-        let mut bottle1= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let mut bottle2= Bottle::newChars([ ' ', ' ', ' ', ' ']);
-        let bottle3= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let bottle4= Bottle::newChars([ ' ', ' ', ' ', ' ']);
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let mut bottle2= Bottle::newChars(&[ ' ', ' ', ' ', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle4= Bottle::newChars(&[ ' ', ' ', ' ', ' ']);
         let mutated= bottle1.fillFrom(&mut bottle2);
-        assert!(mutated == false);
+        assert!(!mutated);
         assert_eq!(bottle1, bottle3);
         assert_eq!(bottle2, bottle4);
     }
 
     #[test]
     fn fillIncompatibleColors() {
-        let mut bottle1= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let mut bottle2= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle3= Bottle::newChars([ 'A', 'A', ' ', ' ']);
-        let bottle4= Bottle::newChars([ 'B', 'B', ' ', ' ']);
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let mut bottle2= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
         let mutated= bottle1.fillFrom(&mut bottle2);
-        assert!(mutated == false);
+        assert!(!mutated);
         assert_eq!(bottle1, bottle3);
         assert_eq!(bottle2, bottle4);
     }
 
     #[test]
     fn fillEmptyDestination() {
-        let mut bottle1= Bottle::newChars([ ' ', ' ', ' ', ' ']);
-        let mut bottle2= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle3= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle4= Bottle::newChars([ ' ', ' ', ' ', ' ']);
+        let mut bottle1= Bottle::newChars(&[ ' ', ' ', ' ', ' ']);
+        let mut bottle2= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle3= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle4= Bottle::newChars(&[ ' ', ' ', ' ', ' ']);
         let mutated= bottle1.fillFrom(&mut bottle2);
-        assert!(mutated == true);
+        assert!(mutated);
         assert_eq!(bottle1, bottle3);
         assert_eq!(bottle2, bottle4);
     }
 
     #[test]
     fn fillAlreadyFull() {
-        let mut bottle1= Bottle::newChars([ 'A', 'A', 'B', 'B']);
-        let mut bottle2= Bottle::newChars([ 'B', 'B', ' ', ' ']);
-        let bottle3= Bottle::newChars([ 'A', 'A', 'B', 'B']);
-        let bottle4= Bottle::newChars([ 'B', 'B', ' ', ' ']);
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', 'B', 'B']);
+        let mut bottle2= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'B', 'B']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
         let mutated= bottle1.fillFrom(&mut bottle2);
-        assert!(mutated == false);
+        assert!(!mutated);
         assert_eq!(bottle1, bottle3);
         assert_eq!(bottle2, bottle4);
     }
 
+    #[test]
+    fn fillWholeCopySingleColor() {
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let mut bottle2= Bottle::newChars(&[ 'B', 'B', 'A', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'A', ' ']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let mutated= bottle1.fillWholeFrom(&mut bottle2);
+        assert!(mutated);
+        assert_eq!(bottle1, bottle3);
+        assert_eq!(bottle2, bottle4);
+    }
+
+    #[test]
+    fn fillWholeAlreadyFull() {
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', 'B', 'B']);
+        let mut bottle2= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'B', 'B']);
+        let bottle4= Bottle::newChars(&[ 'B', 'B', ' ', ' ']);
+        let mutated= bottle1.fillWholeFrom(&mut bottle2);
+        assert!(!mutated);
+        assert_eq!(bottle1, bottle3);
+        assert_eq!(bottle2, bottle4);
+    }
+
+    #[test]
+    fn fillWholeRunTooLong() {
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', 'B', ' ']);
+        let mut bottle2= Bottle::newChars(&[ 'A', 'B', 'B', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'B', ' ']);
+        let bottle4= Bottle::newChars(&[ 'A', 'B', 'B', ' ']);
+        // `fillFrom` would pour one of the two B's
+        let mutated= bottle1.fillWholeFrom(&mut bottle2);
+        assert!(!mutated);
+        assert_eq!(bottle1, bottle3);
+        assert_eq!(bottle2, bottle4);
+        assert_eq!(bottle2.getTopTowerHeight(), 2);
+        assert_eq!(Bottle::newChars(&[ ' ', ' ']).getTopTowerHeight(), 0);
+    }
+
+    #[test]
+    fn fillRespectsCapacity() {
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', 'A', 'A', ' ', ' ']);
+        let mut bottle2= Bottle::newChars(&[ 'B', 'A', 'A', 'A', ' ', ' ']);
+        let bottle3= Bottle::newChars(&[ 'A', 'A', 'A', 'A', 'A', 'A']);
+        let bottle4= Bottle::newChars(&[ 'B', 'A', ' ', ' ', ' ', ' ']);
+        assert!(bottle1.fillFrom(&mut bottle2));
+        assert_eq!(bottle1, bottle3);
+        assert_eq!(bottle2, bottle4);
+        assert!(bottle1.isSolved());
+        let mut bottle5= Bottle::newChars(&[ 'C', 'C', ' ']);
+        let mut bottle6= Bottle::newChars(&[ 'C', 'C', 'C']);
+        assert!(bottle5.fillFrom(&mut bottle6));
+        assert_eq!(bottle5, Bottle::newChars(&[ 'C', 'C', 'C']));
+        assert_eq!(bottle6, Bottle::newChars(&[ 'C', 'C', ' ']));
+    }
+
+    #[test]
+    fn unfillReversesFill() {
+        // Every bottle of up to 3 slots holding the colors A and B
+        let mut bottles= vec![Bottle::newChars(&[' ', ' ', ' '])];
+        for length in 1..=3 {
+            for colors in 0..(1 << length) {
+                let mut content= [' '; 3];
+                for (i, slot) in content.iter_mut().take(length).enumerate() {
+                    *slot= if colors & (1 << i) == 0 { 'A' } else { 'B' };
+                }
+                bottles.push(Bottle::newChars(&content));
+            }
+        }
+        for destination in &bottles {
+            for source in &bottles {
+                let (mut poured, mut pouredFrom)= (destination.clone(), source.clone());
+                let moved= pouredFrom.getContent().iter().filter(|&&slot| slot != EMPTY).count();
+                if poured.fillFrom(&mut pouredFrom) {
+                    let moved= moved - pouredFrom.getContent().iter().filter(|&&slot| slot != EMPTY).count();
+                    assert!(poured.unfillInto(&mut pouredFrom, moved));
+                    assert_eq!((&poured, &pouredFrom), (destination, source));
+                }
+                for amount in 0..=3 {
+                    let (mut undone, mut undoneTo)= (destination.clone(), source.clone());
+                    if undone.unfillInto(&mut undoneTo, amount) {
+                        assert!(undone.fillFrom(&mut undoneTo));
+                        assert_eq!((&undone, &undoneTo), (destination, source), "undoing {} from {:?} to {:?}", amount, destination, source);
+                    } else {
+                        assert_eq!((&undone, &undoneTo), (destination, source));
+                    }
+                }
+            }
+        }
+        // The pour stopped at a full bottle, or moved the whole tower
+        let mut full= Bottle::newChars(&['A', 'A', 'A']);
+        assert!(full.clone().unfillInto(&mut Bottle::newChars(&['A', ' ', ' ']), 1));
+        assert!(!Bottle::newChars(&['A', 'A', ' ']).unfillInto(&mut Bottle::newChars(&['A', ' ', ' ']), 1));
+        assert!(!full.unfillInto(&mut Bottle::newChars(&['B', 'B', ' ']), 2));
+        assert!(full.unfillInto(&mut Bottle::newChars(&[' ', ' ', ' ']), 3));
+        assert!(full.isEmpty());
+    }
+
+    #[test]
+    fn fillSingleUnit() {
+        let mut bottle1= Bottle::newChars(&[ 'A', 'A', ' ', ' ']);
+        let mut bottle2= Bottle::newChars(&[ 'B', 'A', 'A', ' ']);
+        assert!(bottle1.fillOneFrom(&mut bottle2));
+        assert_eq!(bottle1, Bottle::newChars(&[ 'A', 'A', 'A', ' ']));
+        assert_eq!(bottle2, Bottle::newChars(&[ 'B', 'A', ' ', ' ']));
+        assert!(bottle1.fillOneFrom(&mut bottle2));
+        assert!(!bottle1.fillOneFrom(&mut bottle2));
+        assert!(!bottle2.fillOneFrom(&mut bottle1));
+        assert!(bottle1.unfillOneInto(&mut bottle2));
+        assert_eq!(bottle2, Bottle::newChars(&[ 'B', 'A', ' ', ' ']));
+        // The slot below a poured one holds another color, so it cannot have been poured there
+        assert!(!bottle2.unfillOneInto(&mut bottle1));
+        assert!(!Bottle::newChars(&[ ' ', ' ']).fillOneFrom(&mut Bottle::newChars(&[ ' ', ' '])));
+    }
+
+    #[test]
+    fn hiddenSlots() {
+        let mut bottle1= Bottle::newChars(&[ '?', '?', 'A', 'A']);
+        let mut bottle2= Bottle::newChars(&[ ' ', ' ', ' ', ' ']);
+        assert!(!bottle1.isTopHidden());
+        assert!(bottle2.fillFrom(&mut bottle1));
+        assert_eq!(bottle1, Bottle::newChars(&[ '?', '?', ' ', ' ']));
+        assert!(bottle1.isTopHidden());
+        assert!(!bottle1.isSolved());
+        assert!(!Bottle::newChars(&[ '?', '?', '?', '?']).isSolved());
+    }
+
+    #[test]
+    fn mixedSizes() {
+        let bottles= [ Bottle::new(b"AAA"), Bottle::new(b"BBBBBB"), Bottle::new(b"   ") ];
+        assert!(checkColorCounts(&bottles).is_ok());
+        assert!(isSolvedPosition(&bottles));
+        // A color of 6 split over two bottles of 3 is not gathered yet
+        let split= [ Bottle::new(b"BBB"), Bottle::new(b"AAA   "), Bottle::new(b"BBB") ];
+        assert!(checkColorCounts(&split).is_ok());
+        assert!(!isSolvedPosition(&split));
+        // Every color needs a bottle of its own size, not only enough slots
+        assert!(checkColorCounts(&[ Bottle::new(b"AAAB"), Bottle::new(b"ABB   ") ]).unwrap_err().starts_with("Error, the position is invalid! Each color must fill a bottle of 4 or 6 exactly."));
+        assert_eq!(checkColorCounts(&[ Bottle::new(b"AAB"), Bottle::new(b"BBA   ") ]),
+                   Err("Error, the position is invalid! 2 colors must each fill a bottle of 3, but the puzzle has 1 of that size".to_string()));
+    }
+
+    #[test]
+    fn levelAndMonochrome() {
+        assert_eq!(Bottle::newChars(&[ ' ', ' ', ' ']).getLevel(), 0);
+        assert_eq!(Bottle::newChars(&[ 'A', 'B', ' ']).getLevel(), 2);
+        assert!(Bottle::newChars(&[ 'A', 'A', ' ']).isMonochrome());
+        assert!(Bottle::newChars(&[ 'A', 'A', 'A']).isMonochrome());
+        assert!(!Bottle::newChars(&[ 'A', 'B', ' ']).isMonochrome());
+        assert!(!Bottle::newChars(&[ ' ', ' ', ' ']).isMonochrome());
+        assert!(!Bottle::newChars(&[ '?', '?', ' ']).isMonochrome());
+    }
+
     #[test]
     fn checkTowers() {
-        assert_eq!(Bottle::getColorTowers(&Bottle::newChars([ 'A', 'A', ' ', ' '])), 1);
-        assert_eq!(Bottle::getColorTowers(&Bottle::newChars([ 'B', 'B', 'A', ' '])), 2);
-        assert_eq!(Bottle::getColorTowers(&Bottle::newChars([ 'A', 'B', 'D', 'C'])), 4);
-        assert_eq!(Bottle::getColorTowers(&Bottle::newChars([ ' ', ' ', ' ', ' '])), 0);
+        assert_eq!(Bottle::getColorTowers(&Bottle::newChars(&[ 'A', 'A', ' ', ' '])), 1);
+        assert_eq!(Bottle::getColorTowers(&Bottle::newChars(&[ 'B', 'B', 'A', ' '])), 2);
+        assert_eq!(Bottle::getColorTowers(&Bottle::newChars(&[ 'A', 'B', 'D', 'C'])), 4);
+        assert_eq!(Bottle::getColorTowers(&Bottle::newChars(&[ ' ', ' ', ' ', ' '])), 0);
+        assert_eq!(Bottle::getColorTowers(&Bottle::newChars(&[ 'A', 'B', 'B', 'C', 'C', ' '])), 3);
+    }
+
+    #[test]
+    fn locksOpen() {
+        let mut bottles= [ Bottle::new(b"AB ").withLock(Lock::Moves(2)), Bottle::new(b"AAB").withLock(Lock::Color(b'B')),
+                           Bottle::new(b"BB "), Bottle::new(b"A  ").withLock(Lock::Color(b'A')) ];
+        openLocks(&mut bottles);
+        assert!(bottles[1].isLocked() && bottles[3].isLocked());
+        passMove(&mut bottles);
+        assert_eq!(bottles[0].lock, Lock::Moves(1));
+        bottles[2].content[2]= b'B';
+        passMove(&mut bottles);
+        assert_eq!(bottles.iter().map(|bottle| bottle.lock).collect::<Vec<_>>(),
+                   vec![ Lock::Open, Lock::Open, Lock::Open, Lock::Color(b'A') ]);
+        assert_eq!(format!("{:^3}|{}|{}", Lock::Moves(3), Lock::Color(b'R'), Lock::Open), "@3 |@R|");
     }
 }
@@ -0,0 +1,110 @@
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use crate::stats::*;
+use crate::traits::position::*;
+
+/// How many expansions pass between two checks of the clock and the memory estimate
+pub const LIMIT_CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Optional caps on the resources a search may use. `None` means unlimited
+pub struct SearchLimits {
+    pub maxExpanded: Option<u64>, // Maximum number of positions to expand
+    pub maxMemory: Option<usize>, // Approximate maximum size of the collision sets and open positions, in bytes
+    pub timeout: Option<Duration>, // Maximum wall-clock time of the search
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The limit that stopped a search
+pub enum Limit {
+    Expanded,
+    Memory,
+    Time,
+}
+
+impl Limit {
+    /// Returns the name of the limit as used in JSON output
+    pub fn getName(&self) -> &'static str {
+        match self {
+            Limit::Expanded => "nodes",
+            Limit::Memory => "memory",
+            Limit::Time => "time",
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Expanded => write!(f, "node"),
+            Limit::Memory => write!(f, "memory"),
+            Limit::Time => write!(f, "time"),
+        }
+    }
+}
+
+/// The result of running a search
+pub enum SearchOutcome {
+    Solved(Vec<Rc<dyn Position>>), // The path from the initial position to the goal
+    Exhausted, // Every reachable position was visited without reaching the goal
+    BudgetExceeded(Limit, Vec<Rc<dyn Position>>), // The path to the most promising position seen before the limit hit
+}
+
+impl SearchLimits {
+    #[inline]
+    /// Returns `true` if no limit is set
+    pub fn isUnlimited(&self) -> bool {
+        self.maxExpanded.is_none() && self.maxMemory.is_none() && self.timeout.is_none()
+    }
+
+    /// Checks the counters of a running search against the limits. The clock and the memory
+    /// estimate are only consulted every `LIMIT_CHECK_INTERVAL` expansions to keep the check cheap.
+    ///
+    /// # Arguments
+    /// * `stats` - The counters of the search so far.
+    /// * `start` - When the search started.
+    /// * `memory` - Returns the current memory estimate in bytes. Only called when needed.
+    ///
+    /// # Returns
+    /// The limit that has been exceeded, if any.
+    pub fn check(&self, stats: &SearchStats, start: Instant, memory: impl FnOnce() -> usize) -> Option<Limit> {
+        if self.maxExpanded.is_some_and(|maxExpanded| stats.expanded >= maxExpanded) {
+            return Some(Limit::Expanded);
+        }
+        if !stats.expanded.is_multiple_of(LIMIT_CHECK_INTERVAL) {
+            return None;
+        }
+        if self.timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            return Some(Limit::Time);
+        }
+        if self.maxMemory.is_some_and(|maxMemory| memory() >= maxMemory) {
+            return Some(Limit::Memory);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn checkLimits() {
+        let start= Instant::now();
+        let stats= SearchStats { expanded: 10, ..SearchStats::default() };
+        assert!(SearchLimits::default().isUnlimited());
+        assert_eq!(SearchLimits::default().check(&stats, start, || usize::MAX), None);
+        let limits= SearchLimits { maxExpanded: Some(10), ..SearchLimits::default() };
+        assert_eq!(limits.check(&stats, start, || 0), Some(Limit::Expanded));
+        let limits= SearchLimits { maxMemory: Some(100), timeout: Some(Duration::ZERO), ..SearchLimits::default() };
+        // Clock and memory are only checked every LIMIT_CHECK_INTERVAL expansions
+        assert_eq!(limits.check(&stats, start, || 1000), None);
+        let stats= SearchStats { expanded: LIMIT_CHECK_INTERVAL, ..SearchStats::default() };
+        assert_eq!(limits.check(&stats, start, || 1000), Some(Limit::Time));
+        let limits= SearchLimits { maxMemory: Some(100), ..SearchLimits::default() };
+        assert_eq!(limits.check(&stats, start, || 1000), Some(Limit::Memory));
+        assert_eq!(limits.check(&stats, start, || 10), None);
+    }
+}
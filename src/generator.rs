@@ -0,0 +1,133 @@
+use crate::bottle::*;
+use crate::puzzle::*;
+use crate::solver::*;
+
+/// Characters used for the colors of generated puzzles, in order of use
+pub const COLOR_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// A small, fast and reproducible pseudo random number generator (SplitMix64). Puzzles generated
+/// from the same seed are identical on every platform.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn nextU64(&mut self) -> u64 {
+        self.state= self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z= self.state;
+        z= (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z= (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..bound`
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.nextU64() % bound as u64) as usize
+    }
+
+    /// Shuffles a slice in place (Fisher-Yates)
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Settings for `generate`
+pub struct GeneratorOptions {
+    pub colors: usize, // Number of colors, each filling one bottle
+    pub emptyBottles: usize, // Number of extra empty bottles
+    pub capacity: usize, // Number of slots in each bottle
+    pub seed: u64,
+    pub solvable: bool, // Only return puzzles A* can solve
+    pub targetMoves: Option<usize>, // Only return puzzles whose optimal solution has this many moves. Implies `solvable`
+    pub maxAttempts: usize, // How many candidates to try before giving up
+}
+
+/// A generated puzzle along with what is known about it
+pub struct GeneratedPuzzle {
+    pub puzzle: Puzzle,
+    pub optimalMoves: Option<usize>, // Known if solvability was checked
+    pub attempts: usize, // How many candidates were generated
+}
+
+/// Generates a random valid puzzle: every color fills exactly one bottle, shuffled across the filled
+/// bottles, followed by the empty bottles. If `solvable` or `targetMoves` is requested, candidates
+/// are solved with A* until one qualifies.
+///
+/// # Returns
+/// `Ok(GeneratedPuzzle)`, or `Err(String)` if the options are out of range or no candidate qualified
+/// within `maxAttempts`.
+pub fn generate(options: &GeneratorOptions) -> Result<GeneratedPuzzle, String> {
+    if options.colors == 0 || options.colors > COLOR_ALPHABET.len() {
+        return Err(format!("Error: The number of colors must be between 1 and {}.", COLOR_ALPHABET.len()));
+    }
+    if options.capacity == 0 || options.capacity > MAX_CAPACITY {
+        return Err(format!("Error: Bottle capacity must be between 1 and {}.", MAX_CAPACITY));
+    }
+    let mut rng= Rng::new(options.seed);
+    let checkSolution= options.solvable || options.targetMoves.is_some();
+    for attempt in 1..=options.maxAttempts.max(1) {
+        let puzzle= randomPuzzle(options, &mut rng);
+        if !checkSolution {
+            return Ok(GeneratedPuzzle { puzzle, optimalMoves: None, attempts: attempt });
+        }
+        let Ok(solution)= solve(&puzzle, &SolveOptions::default()) else {
+            continue;
+        };
+        let moves= solution.moveCount();
+        if options.targetMoves.is_none_or(|target| target == moves) {
+            return Ok(GeneratedPuzzle { puzzle, optimalMoves: Some(moves), attempts: attempt });
+        }
+    }
+    Err(format!("Error: No qualifying puzzle was found in {} attempts.", options.maxAttempts.max(1)))
+}
+
+/// Deals `capacity` units of every color randomly into `colors` bottles and appends the empty ones
+fn randomPuzzle(options: &GeneratorOptions, rng: &mut Rng) -> Puzzle {
+    let mut units: Vec<u8>= COLOR_ALPHABET[..options.colors]
+        .iter()
+        .flat_map(|&color| std::iter::repeat_n(color, options.capacity))
+        .collect();
+    rng.shuffle(&mut units);
+    let mut bottles: Vec<Bottle>= units.chunks(options.capacity).map(Bottle::new).collect();
+    bottles.extend(std::iter::repeat_n(Bottle::new(&vec![EMPTY; options.capacity]), options.emptyBottles));
+    let count= bottles.len();
+    Puzzle::new(bottles, vec![count])
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::astar::position_astar::*;
+
+    fn options(seed: u64) -> GeneratorOptions {
+        GeneratorOptions { colors: 5, emptyBottles: 2, capacity: 4, seed, solvable: false, targetMoves: None, maxAttempts: 100 }
+    }
+
+    #[test]
+    fn reproducible() {
+        let first= generate(&options(7)).unwrap().puzzle;
+        assert_eq!(first, generate(&options(7)).unwrap().puzzle);
+        assert_ne!(first, generate(&options(8)).unwrap().puzzle);
+        assert_eq!(first.bottles.len(), 7);
+        assert!(PositionAstar::new(first.bottles.clone()).isValid().is_ok());
+        assert_eq!(Puzzle::parse(&first.toText(), None).unwrap(), first);
+    }
+
+    #[test]
+    fn solvableWithTarget() {
+        let generated= generate(&GeneratorOptions { solvable: true, ..options(1) }).unwrap();
+        let moves= generated.optimalMoves.unwrap();
+        assert_eq!(solve(&generated.puzzle, &SolveOptions::default()).unwrap().moveCount(), moves);
+        let targeted= generate(&GeneratorOptions { targetMoves: Some(moves), ..options(2) }).unwrap();
+        assert_eq!(targeted.optimalMoves, Some(moves));
+        assert!(generate(&GeneratorOptions { colors: 0, ..options(1) }).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem::size_of;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use crate::astar::position_astar::*;
+use crate::bottle::*;
+use crate::budget::*;
+use crate::identity::*;
+use crate::rules::*;
+use crate::stats::*;
+use crate::traits::position::*;
+
+/// Maximum number of reachable positions collected to tell an unsolvable puzzle apart
+pub const EXHAUSTION_CHECK_ENTRIES: usize = 1 << 20;
+
+/// The positions reachable from the root collected so far, see `IDAstar`
+struct Reachable {
+    visited: IdentitySet,
+    queue: VecDeque<Vec<Bottle>>, // Collected positions whose successors are not collected yet
+}
+
+/// How a depth-first pass below some position ended
+enum Pass {
+    Found(Rc<PositionAstar>), // A goal was reached
+    Exceeded(u32), // No goal within the threshold. Holds the lowest cost beyond it, `u32::MAX` if none
+    Stopped(Limit), // A search limit was hit
+}
+
+/// Iterative-deepening A*: repeated depth-first searches with a growing bound on the projected cost.
+/// Only the current path and the children of its positions are kept in memory, so memory grows with
+/// the length of the solution rather than with the number of positions visited. Positions already on
+/// the current path are skipped, so every pass ends even where pours can undo each other over several
+/// moves. An optional, bounded transposition table skips positions already explored at a lower cost in
+/// the same pass.
+///
+/// Walking every path of an unsolvable puzzle can still take far too long, so between passes the
+/// positions reachable from the root are also collected breadth first, as many more as the pass
+/// before expanded. Once they are all collected without a solved one the search ends. Puzzles with
+/// more than `EXHAUSTION_CHECK_ENTRIES` reachable positions give up that check.
+pub struct IDAstar {
+    root: Rc<PositionAstar>,
+    codec: ColorCodec,
+    // Cheapest cost each position was reached with in the current pass, at most `tableSize` entries
+    table: HashMap<Box<[u64]>, u32>,
+    tableSize: usize,
+    // Keys of the positions on the current path, the root included
+    path: HashSet<Box<[u64]>>,
+    // `None` once a solved position or more than `EXHAUSTION_CHECK_ENTRIES` positions were collected
+    reachable: Option<Reachable>,
+    stats: SearchStats,
+    limits: SearchLimits,
+    // The expanded position closest to the goal according to the heuristic, reported when a limit is hit
+    best: Rc<PositionAstar>,
+    // Depth of the current path, used to estimate memory
+    depth: usize,
+    heuristic: Heuristic,
+    rules: Rules,
+    progress: Progress,
+}
+
+impl IDAstar {
+    /// Constructs a new IDAstar instance without a transposition table.
+    ///
+    /// # Arguments
+    /// * `initialPosition` - The starting point of the search.
+    pub fn new(initialPosition: PositionAstar) -> Self {
+        let codec= ColorCodec::new(initialPosition.getBottles());
+        let mut visited= IdentitySet::new(initialPosition.getBottles());
+        visited.insert(initialPosition.getBottles());
+        let reachable= Reachable { visited, queue: VecDeque::from([initialPosition.getBottles().clone()]) };
+        let root= Rc::new(initialPosition);
+        Self { root: root.clone(),
+               codec,
+               table: HashMap::new(),
+               tableSize: 0,
+               path: HashSet::new(),
+               reachable: Some(reachable),
+               stats: SearchStats::default(),
+               limits: SearchLimits::default(),
+               best: root,
+               depth: 0,
+               heuristic: Heuristic::default(),
+               rules: Rules::default(),
+               progress: Progress::default() }
+    }
+
+    /// Guides the search with another heuristic than `Heuristic::Towers`
+    pub fn setHeuristic(&mut self, heuristic: Heuristic) {
+        self.heuristic= heuristic;
+        self.root= Rc::new(PositionAstar::newWithHeuristic(self.root.getBottles().clone(), heuristic, &self.rules));
+        self.best= self.root.clone();
+    }
+
+    /// Chooses which pours are made, by default with pruning
+    pub fn setRules(&mut self, rules: Rules) {
+        self.rules= rules;
+        self.setHeuristic(self.heuristic);
+    }
+
+    /// Enables a transposition table of at most `entries` positions. 0 disables it.
+    pub fn setTranspositionTable(&mut self, entries: usize) {
+        self.tableSize= entries;
+    }
+
+    /// Caps the resources the search may use. Unlimited by default.
+    pub fn setLimits(&mut self, limits: SearchLimits) {
+        self.limits= limits;
+    }
+
+    /// Prints the counters of the search to stderr every `interval` while it runs. Quiet by default.
+    pub fn setProgress(&mut self, interval: Option<Duration>) {
+        self.progress= Progress::new(interval);
+    }
+
+    /// Returns the counters of the search so far
+    pub fn getStats(&self) -> SearchStats {
+        self.stats
+    }
+
+    /// Executes the IDA* algorithm to find a solution.
+    ///
+    /// # Returns
+    /// `SearchOutcome::Solved` with the sequence of positions solving the puzzle if a solution is found,
+    /// `SearchOutcome::Exhausted` if no solution is possible, or `SearchOutcome::BudgetExceeded` with the
+    /// path to the most promising position if a limit was hit first.
+    pub fn solve(&mut self) -> SearchOutcome {
+        let start= Instant::now();
+        let result= self.search(start);
+        self.stats.recordPeaks(0, self.getMemoryEstimate());
+        self.stats.elapsed += start.elapsed();
+        result
+    }
+
+    fn search(&mut self, start: Instant) -> SearchOutcome {
+        let mut threshold= self.root.totalProjectedCost;
+        loop {
+            self.table.clear();
+            self.path= HashSet::from([self.codec.getKey(self.root.getBottles())]);
+            let expanded= self.stats.expanded;
+            match self.deepen(&self.root.clone(), threshold, start) {
+                Pass::Found(goal) => return SearchOutcome::Solved(IDAstar::buildSolutionVector(goal)),
+                Pass::Exceeded(u32::MAX) => return SearchOutcome::Exhausted,
+                Pass::Exceeded(_) if self.collectReachable(self.stats.expanded - expanded) => return SearchOutcome::Exhausted,
+                Pass::Exceeded(next) => threshold= next,
+                Pass::Stopped(limit) => return SearchOutcome::BudgetExceeded(limit, IDAstar::buildSolutionVector(self.best.clone())),
+            }
+        }
+    }
+
+    /// Searches depth first below `position`, skipping positions whose projected cost exceeds
+    /// `threshold`. Children are tried in order of their projected cost.
+    fn deepen(&mut self, position: &Rc<PositionAstar>, threshold: u32, start: Instant) -> Pass {
+        if position.totalProjectedCost > threshold {
+            return Pass::Exceeded(position.totalProjectedCost);
+        }
+        if position.isSolved() {
+            return Pass::Found(position.clone());
+        }
+        if let Some(limit)= self.limits.check(&self.stats, start, || self.getMemoryEstimate()) {
+            return Pass::Stopped(limit);
+        }
+        if self.stats.expanded.is_multiple_of(LIMIT_CHECK_INTERVAL) {
+            self.stats.recordPeaks(0, self.getMemoryEstimate());
+            self.progress.report("IDA*", &self.stats, start);
+        }
+        if position.getHeuristic() < self.best.getHeuristic() {
+            self.best= position.clone();
+        }
+        self.stats.expanded += 1;
+        let mut children= PositionAstar::getNextPossiblePositions(position, self.heuristic, &self.rules);
+        children.sort_by_key(|child| child.totalProjectedCost);
+        let mut next= u32::MAX;
+        for child in children {
+            self.stats.generated += 1;
+            if child.isDeadEnd() {
+                continue;
+            }
+            // Coming back to a position on the path, e.g. by pouring straight back, never helps
+            let key= self.codec.getKey(child.getBottles());
+            if self.path.contains(&key) || !self.isCheapest(&key, child.currentCost) {
+                self.stats.duplicates += 1;
+                continue;
+            }
+            self.path.insert(key.clone());
+            self.depth += 1;
+            // The frontier of a depth first search is the current path
+            self.stats.peakFrontier= self.stats.peakFrontier.max(self.depth);
+            let pass= self.deepen(&child, threshold, start);
+            self.depth -= 1;
+            self.path.remove(&key);
+            match pass {
+                Pass::Exceeded(cost) => next= next.min(cost),
+                pass => return pass,
+            }
+        }
+        Pass::Exceeded(next)
+    }
+
+    /// Collects up to `count` more positions reachable from the root, see `IDAstar`.
+    ///
+    /// # Returns
+    /// `true` if all of them are collected and none is solved, i.e. the puzzle cannot be solved.
+    fn collectReachable(&mut self, count: u64) -> bool {
+        let Some(reachable)= &mut self.reachable else {
+            return false;
+        };
+        for _ in 0..count {
+            let Some(bottles)= reachable.queue.pop_front() else {
+                return true;
+            };
+            let position= Rc::new(PositionAstar::new(bottles));
+            if position.isSolved() || reachable.visited.len() > EXHAUSTION_CHECK_ENTRIES {
+                self.reachable= None;
+                return false;
+            }
+            for child in PositionAstar::getNextPossiblePositions(&position, Heuristic::default(), &self.rules) {
+                if !child.isDeadEnd() && reachable.visited.insert(child.getBottles()) {
+                    reachable.queue.push_back(child.getBottles().clone());
+                }
+            }
+        }
+        reachable.queue.is_empty()
+    }
+
+    /// Records the cost a position is reached with in the transposition table.
+    ///
+    /// # Returns
+    /// `false` if the position was already explored in this pass at no higher cost, in which case
+    /// exploring it again cannot find anything new.
+    fn isCheapest(&mut self, key: &[u64], currentCost: u32) -> bool {
+        if self.tableSize == 0 {
+            return true;
+        }
+        match self.table.get_mut(key) {
+            Some(cost) if *cost <= currentCost => false,
+            Some(cost) => {
+                *cost= currentCost;
+                true
+            },
+            None => {
+                if self.table.len() < self.tableSize {
+                    self.table.insert(key.into(), currentCost);
+                }
+                true
+            },
+        }
+    }
+
+    /// Approximates the memory held by the search: the positions along the current path with their
+    /// pending children and keys, plus the transposition table and the reachable positions collected
+    fn getMemoryEstimate(&self) -> usize {
+        let bottles= self.root.getBottles().len();
+        let position= size_of::<PositionAstar>() + 2 * size_of::<usize>() + bottles * size_of::<Bottle>();
+        let key= size_of::<Box<[u64]>>() + (bottles * MAX_CAPACITY).div_ceil(8) + size_of::<u32>() + 2 * size_of::<usize>();
+        let reachable= self.reachable.as_ref().map_or(0, |reachable| {
+            reachable.visited.len() * reachable.visited.getBytesPerPosition() + reachable.queue.len() * bottles * size_of::<Bottle>()
+        });
+        (self.depth + 1) * (bottles * bottles * position + key) + self.table.len() * key + reachable
+    }
+
+    /// Constructs a vector of `Position` objects representing the path from the initial position to
+    /// the given position.
+    fn buildSolutionVector(position: Rc<PositionAstar>) -> Vec<Rc<dyn Position>> {
+        let mut result: Vec<Rc<dyn Position>>= Vec::new();
+        let mut current= Some(position);
+        while let Some(position)= current {
+            current= position.previous.clone();
+            result.push(position);
+        }
+        result.reverse();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::astar::astar::*;
+    use crate::puzzle::*;
+
+    fn moveCount(outcome: SearchOutcome) -> usize {
+        match outcome {
+            SearchOutcome::Solved(positions) => positions.len() - 1,
+            _ => panic!("The puzzle is solvable"),
+        }
+    }
+
+    #[test]
+    fn sameLengthAsAstar() {
+        let bottles: Vec<Bottle>= ["ABCA", "BCAB", "CABC", "    ", "    "].iter().map(|bottle| Bottle::new(bottle.as_bytes())).collect();
+        let astar= moveCount(Astar::new(PositionAstar::new(bottles.clone())).solve());
+        let mut plain= IDAstar::new(PositionAstar::new(bottles.clone()));
+        assert_eq!(moveCount(plain.solve()), astar);
+        let mut cached= IDAstar::new(PositionAstar::new(bottles.clone()));
+        cached.setTranspositionTable(1000);
+        assert_eq!(moveCount(cached.solve()), astar);
+        assert!(cached.getStats().expanded <= plain.getStats().expanded);
+        // A tiny table fills up but stays correct
+        let mut tiny= IDAstar::new(PositionAstar::new(bottles));
+        tiny.setTranspositionTable(3);
+        assert_eq!(moveCount(tiny.solve()), astar);
+    }
+
+    #[test]
+    fn unsolvable() {
+        let bottles= vec![Bottle::new(b"AB"), Bottle::new(b"BA")];
+        assert!(matches!(IDAstar::new(PositionAstar::new(bottles)).solve(), SearchOutcome::Exhausted));
+        // Single unit pours can undo each other over several moves
+        let bottles: Vec<Bottle>= ["BAA", "BCC", "BAC", "   "].iter().map(|bottle| Bottle::new(bottle.as_bytes())).collect();
+        let mut idastar= IDAstar::new(PositionAstar::new(bottles));
+        idastar.setRules(Rules { pour: PourRule::SingleUnit, ..Rules::default() });
+        assert!(matches!(idastar.solve(), SearchOutcome::Exhausted));
+        // Mixed sizes and locks leave far too many paths to walk them all
+        for (text, pour) in [("BA/3;A/1;BA/3;CCBC/4;A/2", PourRule::Run), ("CA/2;BA/3;CA/2;BC/4@3;A/1", PourRule::Run),
+                             ("ABC/3@1;/4;CB/4@2;AB/2@A;CB/3", PourRule::SingleUnit)] {
+            let puzzle= Puzzle::parse(text, None).unwrap();
+            let mut idastar= IDAstar::new(PositionAstar::new(puzzle.bottles));
+            idastar.setRules(Rules { pour, ..Rules::default() });
+            assert!(matches!(idastar.solve(), SearchOutcome::Exhausted), "{}", text);
+            assert!(idastar.getStats().expanded < 10_000, "{}", text);
+        }
+    }
+}
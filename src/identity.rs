@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::hash::Hash;
+use std::mem::size_of;
+use std::sync::Mutex;
+use crate::bottle::*;
+
+/// Maps the colors of a puzzle to small consecutive codes, so a slot takes only as many bits as
+/// needed to tell the colors apart. The empty slot is always code 0.
+#[derive(Debug, Clone)]
+pub struct ColorCodec {
+    codes: [u8; 256], // Code of every byte that may appear in a slot
+    bits: u32, // Bits per slot
+    maxMoves: u16, // Longest move lock, lock codes above it are color locks
+    lockBits: u32, // Bits per bottle for its lock, 0 if the puzzle has no locked bottles
+}
+
+impl ColorCodec {
+    /// Assigns a code to every color found in the bottles. Pours never create new colors, so the
+    /// codec of the initial position covers every position reachable from it. The same holds for
+    /// locks, which only ever count down or open.
+    pub fn new(bottles: &[Bottle]) -> Self {
+        let mut codes= [0; 256];
+        let mut next: u8= 1;
+        for bottle in bottles {
+            for &slot in bottle.getContent() {
+                if slot != EMPTY && codes[slot as usize] == 0 {
+                    codes[slot as usize]= next;
+                    next += 1;
+                }
+            }
+        }
+        // `next` codes are in use, including the empty slot
+        let bits= (u8::BITS - (next - 1).leading_zeros()).max(1);
+        let maxMoves= bottles.iter()
+            .map(|bottle| if let Lock::Moves(moves)= bottle.lock { moves as u16 } else { 0 })
+            .max()
+            .unwrap_or(0);
+        let lockBits= if bottles.iter().any(Bottle::isLocked) {
+            u16::BITS - (maxMoves + next as u16 - 1).leading_zeros()
+        } else {
+            0
+        };
+        Self { codes, bits, maxMoves, lockBits }
+    }
+
+    #[inline]
+    /// Returns the number of bits a slot is packed into
+    pub fn getBits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Packs the content of a bottle into the low bits of an integer, bottom slot in the lowest
+    /// bits, with the capacity in the top byte so bottles of different sizes never compare equal
+    /// and always sort by capacity first.
+    #[inline]
+    fn encodeBottle(&self, bottle: &Bottle) -> u64 {
+        let content= bottle.getContent()
+            .iter()
+            .rev()
+            .fold(0u64, |code, &slot| (code << self.bits) | self.codes[slot as usize] as u64);
+        ((bottle.getCapacity() as u64) << 56) | content
+    }
+
+    /// Returns the lock of a bottle as a number below `1 << lockBits`, 0 for an open bottle
+    #[inline]
+    fn encodeLock(&self, bottle: &Bottle) -> u64 {
+        match bottle.lock {
+            Lock::Open => 0,
+            Lock::Moves(moves) => moves as u64,
+            Lock::Color(color) => (self.maxMoves + self.codes[color as usize] as u16) as u64,
+        }
+    }
+
+    /// Returns the number of 64 bit words the key of a position of these bottles takes
+    fn getWords(&self, bottles: &[Bottle]) -> usize {
+        let slots: usize= bottles.iter().map(Bottle::getCapacity).sum();
+        (slots * self.bits as usize + bottles.len() * self.lockBits as usize).div_ceil(64)
+    }
+
+    /// Returns the canonical key of a position, as stored by `IdentitySet`, in a boxed slice of
+    /// just the number of words the position needs
+    pub fn getKey(&self, bottles: &[Bottle]) -> Box<[u64]> {
+        pack(self, bottles, vec![0; self.getWords(bottles)].into_boxed_slice())
+    }
+
+    /// Hashes a position the same way for all bottle permutations, without sorting
+    fn hashBottles(&self, bottles: &[Bottle]) -> u64 {
+        bottles.iter().fold(0u64, |hash, bottle| {
+            // SplitMix64 finalizer, so similar bottles spread over all bits before they are summed
+            let lock= self.encodeLock(bottle).rotate_right(self.lockBits);
+            let mut code= (self.encodeBottle(bottle) ^ lock).wrapping_add(0x9E3779B97F4A7C15);
+            code= (code ^ (code >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            code= (code ^ (code >> 27)).wrapping_mul(0x94D049BB133111EB);
+            hash.wrapping_add(code ^ (code >> 31))
+        })
+    }
+}
+
+/// A map from positions to values, keyed by a canonical bit-packed key: every bottle is encoded with
+/// the `ColorCodec`, the bottles are sorted so permutations map to the same key, and their slots are
+/// packed back to back into a fixed number of 64 bit words. The number of words is picked once from
+/// the size of the puzzle, so keys are stored inline without a heap allocation per position.
+/// Puzzles too big for the widest fixed key fall back to boxed keys.
+pub struct IdentityMap<V> {
+    codec: ColorCodec,
+    words: usize, // Number of 64 bit words in a key
+    keys: Keys<V>,
+}
+
+/// A set of positions, see `IdentityMap`
+pub type IdentitySet= IdentityMap<()>;
+
+enum Keys<V> {
+    Words2(HashMap<[u64; 2], V>),
+    Words4(HashMap<[u64; 4], V>),
+    Words6(HashMap<[u64; 6], V>),
+    Words8(HashMap<[u64; 8], V>),
+    Wide(HashMap<Box<[u64]>, V>),
+}
+
+impl<V: PartialOrd> IdentityMap<V> {
+    /// Creates an empty map for positions reachable from `bottles`
+    pub fn new(bottles: &[Bottle]) -> Self {
+        let codec= ColorCodec::new(bottles);
+        let words= codec.getWords(bottles);
+        let keys= match words {
+            0..=2 => Keys::Words2(HashMap::new()),
+            3..=4 => Keys::Words4(HashMap::new()),
+            5..=6 => Keys::Words6(HashMap::new()),
+            7..=8 => Keys::Words8(HashMap::new()),
+            _ => Keys::Wide(HashMap::new()),
+        };
+        Self { codec, words, keys }
+    }
+
+    /// Creates an empty map using the same encoding as this one
+    pub fn newEmpty(&self) -> Self {
+        let keys= match self.keys {
+            Keys::Words2(_) => Keys::Words2(HashMap::new()),
+            Keys::Words4(_) => Keys::Words4(HashMap::new()),
+            Keys::Words6(_) => Keys::Words6(HashMap::new()),
+            Keys::Words8(_) => Keys::Words8(HashMap::new()),
+            Keys::Wide(_) => Keys::Wide(HashMap::new()),
+        };
+        Self { codec: self.codec.clone(), words: self.words, keys }
+    }
+
+    /// Stores `value` for a position unless the map already holds a value for it that is not
+    /// greater, e.g. the cost the position was reached with.
+    ///
+    /// # Returns
+    /// `true` if the value was stored, i.e. the position is new or `value` is lower than before.
+    pub fn insertLower(&mut self, bottles: &[Bottle], value: V) -> bool {
+        match &mut self.keys {
+            Keys::Words2(map) => insertLowerInto(map, pack(&self.codec, bottles, [0; 2]), value),
+            Keys::Words4(map) => insertLowerInto(map, pack(&self.codec, bottles, [0; 4]), value),
+            Keys::Words6(map) => insertLowerInto(map, pack(&self.codec, bottles, [0; 6]), value),
+            Keys::Words8(map) => insertLowerInto(map, pack(&self.codec, bottles, [0; 8]), value),
+            Keys::Wide(map) => insertLowerInto(map, pack(&self.codec, bottles, vec![0; self.words].into_boxed_slice()), value),
+        }
+    }
+
+    /// Returns the value stored for a position, if any
+    pub fn get(&self, bottles: &[Bottle]) -> Option<&V> {
+        match &self.keys {
+            Keys::Words2(map) => map.get(&pack(&self.codec, bottles, [0; 2])),
+            Keys::Words4(map) => map.get(&pack(&self.codec, bottles, [0; 4])),
+            Keys::Words6(map) => map.get(&pack(&self.codec, bottles, [0; 6])),
+            Keys::Words8(map) => map.get(&pack(&self.codec, bottles, [0; 8])),
+            Keys::Wide(map) => map.get(&pack(&self.codec, bottles, vec![0; self.words].into_boxed_slice())),
+        }
+    }
+
+    /// Returns the number of positions in the map
+    pub fn len(&self) -> usize {
+        match &self.keys {
+            Keys::Words2(map) => map.len(),
+            Keys::Words4(map) => map.len(),
+            Keys::Words6(map) => map.len(),
+            Keys::Words8(map) => map.len(),
+            Keys::Wide(map) => map.len(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Approximates the bytes the map uses per position: the key and value plus the hash table's
+    /// control byte and spare capacity
+    pub fn getBytesPerPosition(&self) -> usize {
+        let key= match &self.keys {
+            Keys::Words2(_) => size_of::<([u64; 2], V)>(),
+            Keys::Words4(_) => size_of::<([u64; 4], V)>(),
+            Keys::Words6(_) => size_of::<([u64; 6], V)>(),
+            Keys::Words8(_) => size_of::<([u64; 8], V)>(),
+            // Boxed keys add their heap block and the allocator's bookkeeping
+            Keys::Wide(_) => size_of::<(Box<[u64]>, V)>() + self.words * size_of::<u64>() + 2 * size_of::<usize>(),
+        };
+        (key + 1) * 8 / 7
+    }
+}
+
+impl IdentitySet {
+    /// Adds a position to the set.
+    ///
+    /// # Returns
+    /// `true` if no permutation of the bottles was in the set yet.
+    #[inline]
+    pub fn insert(&mut self, bottles: &[Bottle]) -> bool {
+        self.insertLower(bottles, ())
+    }
+}
+
+fn insertLowerInto<K: Hash + Eq, V: PartialOrd>(map: &mut HashMap<K, V>, key: K, value: V) -> bool {
+    match map.entry(key) {
+        Entry::Occupied(mut entry) if value < *entry.get() => {
+            entry.insert(value);
+            true
+        },
+        Entry::Occupied(_) => false,
+        Entry::Vacant(entry) => {
+            entry.insert(value);
+            true
+        },
+    }
+}
+
+/// A thread-safe `IdentitySet` for searches expanding positions on several threads. Positions are
+/// spread over independently locked shards by a permutation invariant hash, so threads inserting
+/// different positions rarely wait for each other.
+pub struct SharedIdentitySet {
+    codec: ColorCodec, // Picks the shard of a position
+    shards: Vec<Mutex<IdentitySet>>,
+}
+
+impl SharedIdentitySet {
+    /// Creates an empty set for positions reachable from `bottles`, split into `shards` shards
+    pub fn new(bottles: &[Bottle], shards: usize) -> Self {
+        let set= IdentitySet::new(bottles);
+        Self { codec: set.codec.clone(),
+               shards: (0..shards.max(1)).map(|_| Mutex::new(set.newEmpty())).collect() }
+    }
+
+    /// Creates an empty set with the same encoding and number of shards as this one
+    pub fn newEmpty(&self) -> Self {
+        Self { codec: self.codec.clone(),
+               shards: self.shards.iter().map(|shard| Mutex::new(shard.lock().unwrap().newEmpty())).collect() }
+    }
+
+    /// Adds a position to the set.
+    ///
+    /// # Returns
+    /// `true` if no permutation of the bottles was in the set yet.
+    pub fn insert(&self, bottles: &[Bottle]) -> bool {
+        let shard= if self.shards.len() == 1 {
+            0
+        } else {
+            (self.codec.hashBottles(bottles) % self.shards.len() as u64) as usize
+        };
+        self.shards[shard].lock().unwrap().insert(bottles)
+    }
+
+    /// Returns the number of positions in the set
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Approximates the bytes the set uses per position
+    pub fn getBytesPerPosition(&self) -> usize {
+        self.shards[0].lock().unwrap().getBytesPerPosition()
+    }
+}
+
+/// Writes the canonical key of a position into `words`, which must be wide enough for every slot
+/// and lock
+fn pack<W: AsMut<[u64]>>(codec: &ColorCodec, bottles: &[Bottle], mut words: W) -> W {
+    let out= words.as_mut();
+    let mut bit= 0;
+    if codec.lockBits == 0 {
+        let mut codes: Vec<u64>= bottles.iter().map(|bottle| codec.encodeBottle(bottle)).collect();
+        codes.sort_unstable();
+        for code in codes {
+            packBits(out, &mut bit, code & ((1u64 << 56) - 1), (code >> 56) as usize * codec.getBits() as usize);
+        }
+    } else {
+        // A lock travels with its bottle, so it is sorted along with it
+        let mut codes: Vec<(u64, u64)>= bottles.iter().map(|bottle| (codec.encodeBottle(bottle), codec.encodeLock(bottle))).collect();
+        codes.sort_unstable();
+        for (code, lock) in codes {
+            packBits(out, &mut bit, code & ((1u64 << 56) - 1), (code >> 56) as usize * codec.getBits() as usize);
+            packBits(out, &mut bit, lock, codec.lockBits as usize);
+        }
+    }
+    words
+}
+
+/// Appends the low `width` bits of `value` to the bits of `out` written so far
+#[inline]
+fn packBits(out: &mut [u64], bit: &mut usize, value: u64, width: usize) {
+    let (word, offset)= (*bit / 64, *bit % 64);
+    out[word] |= value << offset;
+    if offset + width > 64 {
+        out[word + 1] |= value >> (64 - offset);
+    }
+    *bit += width;
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn bottles(text: &str, capacity: usize) -> Vec<Bottle> {
+        text.split(';')
+            .map(|bottle| {
+                let mut content= vec![EMPTY; capacity];
+                content[..bottle.len()].copy_from_slice(bottle.as_bytes());
+                Bottle::new(&content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn codecBits() {
+        assert_eq!(ColorCodec::new(&bottles("A;A;", 1)).getBits(), 1);
+        assert_eq!(ColorCodec::new(&bottles("AB;BA;", 2)).getBits(), 2);
+        assert_eq!(ColorCodec::new(&bottles("ABC;BCA;CAB;", 3)).getBits(), 2);
+        assert_eq!(ColorCodec::new(&bottles("PSED;BPED;GUBP;OHLL;COAL;YYOA;YCUR;YORS;GNNA;CUUS;PNDA;GCEN;GHHD;BBSE;RRHL;;;", 4)).getBits(), 4);
+    }
+
+    #[test]
+    fn permutationsCollide() {
+        let mut set= IdentitySet::new(&bottles("AB;BA;", 2));
+        assert!(set.insert(&bottles("AB;BA;", 2)));
+        assert!(!set.insert(&bottles(";BA;AB", 2)));
+        assert!(set.insert(&bottles("A;BA;B", 2)));
+        assert!(!set.insert(&bottles("B;A;BA", 2)));
+        assert_eq!(set.len(), 2);
+        let mut empty= set.newEmpty();
+        assert!(empty.is_empty());
+        assert!(empty.insert(&bottles("AB;BA;", 2)));
+    }
+
+    #[test]
+    fn sizesNotInterchangeable() {
+        let mut set= IdentitySet::new(&[ Bottle::new(b"AB "), Bottle::new(b"BA    ") ]);
+        assert!(set.insert(&[ Bottle::new(b"AB "), Bottle::new(b"BA    ") ]));
+        assert!(!set.insert(&[ Bottle::new(b"BA    "), Bottle::new(b"AB ") ]));
+        // The same contents in the other bottles is another position
+        assert!(set.insert(&[ Bottle::new(b"BA "), Bottle::new(b"AB    ") ]));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn locksNotInterchangeable() {
+        let initial= [ Bottle::new(b"AB").withLock(Lock::Moves(2)), Bottle::new(b"BA"), Bottle::new(b"  ").withLock(Lock::Color(b'A')) ];
+        let mut set= IdentitySet::new(&initial);
+        assert!(set.insert(&initial));
+        assert!(!set.insert(&[ initial[2].clone(), initial[0].clone(), initial[1].clone() ]));
+        // The lock stays with its bottle, and a lock counting down is another position
+        assert!(set.insert(&[ Bottle::new(b"AB"), Bottle::new(b"BA").withLock(Lock::Moves(2)), initial[2].clone() ]));
+        assert!(set.insert(&[ Bottle::new(b"AB").withLock(Lock::Moves(1)), Bottle::new(b"BA"), initial[2].clone() ]));
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn mapKeepsLowest() {
+        let mut costs= IdentityMap::new(&bottles("AB;BA;", 2));
+        assert!(costs.insertLower(&bottles("A;BA;B", 2), 3));
+        assert!(!costs.insertLower(&bottles("B;A;BA", 2), 3));
+        assert!(!costs.insertLower(&bottles("A;BA;B", 2), 4));
+        assert!(costs.insertLower(&bottles("BA;B;A", 2), 1));
+        assert!(!costs.insertLower(&bottles("A;BA;B", 2), 2));
+        assert_eq!(costs.len(), 1);
+        assert_eq!(costs.get(&bottles("B;A;BA", 2)), Some(&1));
+        assert_eq!(costs.get(&bottles("AB;BA;", 2)), None);
+    }
+
+    #[test]
+    fn keysSpanWords() {
+        // 18 bottles of 4 slots with 16 codes take 288 bits, so bottles straddle word boundaries
+        let initial= bottles("PSED;BPED;GUBP;OHLL;COAL;YYOA;YCUR;YORS;GNNA;CUUS;PNDA;GCEN;GHHD;BBSE;RRHL;;;", 4);
+        let mut set= IdentitySet::new(&initial);
+        assert!(matches!(set.keys, Keys::Words6(_)));
+        assert!(set.insert(&initial));
+        let mut swapped= initial.clone();
+        swapped.swap(0, 16);
+        swapped.swap(3, 9);
+        assert!(!set.insert(&swapped));
+        // Differences in the last bottle and in a bottle crossing a word boundary are told apart
+        let mut changed= initial.clone();
+        changed[17]= Bottle::new(b"D   ");
+        changed[0]= Bottle::new(b"PSE ");
+        assert!(set.insert(&changed));
+        let mut changed= initial.clone();
+        changed[17]= Bottle::new(b"L   ");
+        changed[14]= Bottle::new(b"RRH ");
+        assert!(set.insert(&changed));
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn sharedSetShards() {
+        let initial= bottles("ABC;BCA;CAB;;", 3);
+        let shared= SharedIdentitySet::new(&initial, 4);
+        assert!(shared.insert(&initial));
+        assert!(!shared.insert(&bottles(";CAB;ABC;;BCA", 3)));
+        // Threads inserting overlapping positions agree on exactly one winner per position
+        let rotations: Vec<Vec<Bottle>>= (0..5).map(|shift| {
+            let mut rotated= bottles("ABC;BC;CAB;A;", 3);
+            rotated.rotate_left(shift);
+            rotated
+        }).collect();
+        let winners: usize= std::thread::scope(|scope| {
+            let handles: Vec<_>= rotations.iter().map(|position| scope.spawn(|| shared.insert(position) as usize)).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).sum()
+        });
+        assert_eq!(winners, 1);
+        assert_eq!(shared.len(), 2);
+        assert!(shared.newEmpty().is_empty());
+    }
+
+    #[test]
+    fn wideKeys() {
+        let colors= "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghij";
+        let text: Vec<String>= colors.chars().map(|color| color.to_string().repeat(8)).collect();
+        let initial= bottles(&format!("{};;", text.join(";")), 8);
+        let mut set= IdentitySet::new(&initial);
+        assert!(matches!(set.keys, Keys::Wide(_)));
+        assert!(set.insert(&initial));
+        let mut swapped= initial.clone();
+        swapped.swap(0, 37);
+        assert!(!set.insert(&swapped));
+        let codec= ColorCodec::new(&initial);
+        assert_eq!(codec.getKey(&initial), codec.getKey(&swapped));
+        assert_eq!(codec.getKey(&initial).len(), 29);
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::Write;
+use crate::bottle::*;
+use crate::budget::*;
+use crate::pour::*;
+use crate::puzzle::*;
+use crate::solver::*;
+use crate::stats::*;
+
+/// Serializes a solution into a self-contained JSON document.
+///
+/// Bottles are written as strings listing their content from the bottom up with trailing empty
+/// slots trimmed, the same way they appear in the puzzle input, with their size if the bottles
+/// differ in size and their lock if they are locked. Bottle numbers in moves are
+/// 1-based in input order, matching the text output.
+///
+/// # Arguments
+/// * `solution` - The solution to serialize.
+/// * `rowLayout` - How many bottles each input row holds.
+///
+/// # Returns
+/// A JSON object with the algorithm, move count, proven lower bound of the move count, search statistics, row layout, initial board and
+/// every step with its move and resulting board.
+pub fn solutionToJson(solution: &Solution, rowLayout: &[usize]) -> String {
+    writeSolution(solution, rowLayout, "")
+}
+
+/// Serializes a solution spending extra tubes like `solutionToJson`, adding the tubes as
+/// `"extraTubes":[{"bottle":10,"beforeStep":4}]`, 1-based bottle numbers with the step each tube must
+/// be added before, empty if none is needed. The row layout includes the row of extra tubes.
+pub fn extraTubesToJson(extraTubes: &ExtraTubes) -> String {
+    let firstTube= extraTubes.puzzle.bottles.len() - extraTubes.count();
+    let tubes: Vec<String>= extraTubes.addBefore
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format!("{{\"bottle\":{},\"beforeStep\":{}}}", firstTube + i + 1, step))
+        .collect();
+    writeSolution(&extraTubes.solution, &extraTubes.puzzle.rowLayout, &format!(",\"extraTubes\":[{}]", tubes.join(",")))
+}
+
+/// Writes the JSON object of a solution with `extra` fields after the lower bound
+fn writeSolution(solution: &Solution, rowLayout: &[usize], extra: &str) -> String {
+    let mut out= String::new();
+    write!(out, "{{\"solved\":true,\"algorithm\":\"{}\",\"moveCount\":{},\"lowerBound\":{}{},\"stats\":{},\"rowLayout\":[{}],\"initial\":{},\"steps\":[",
+           solution.algorithm.getName(),
+           solution.moveCount(),
+           solution.lowerBound,
+           extra,
+           statsToJson(&solution.stats),
+           rowLayout.iter().map(|row| row.to_string()).collect::<Vec<_>>().join(","),
+           bottlesToJson(solution.positions[0].getBottles())).unwrap();
+    let boards: Vec<&[Bottle]>= solution.positions.iter().map(|position| position.getBottles().as_slice()).collect();
+    writeSteps(&mut out, &boards);
+    out.push_str("]}");
+    out
+}
+
+/// Serializes the outcome of a search stopped by a limit, including the path to the most promising
+/// position it found in the same format as the steps of a solution
+pub fn budgetExceededToJson(algorithm: Algorithm, limit: Limit, partial: &[Vec<Bottle>], stats: &SearchStats, rowLayout: &[usize]) -> String {
+    let mut out= String::new();
+    write!(out, "{{\"solved\":false,\"algorithm\":\"{}\",\"budgetExceeded\":\"{}\",\"partialMoveCount\":{},\"stats\":{},\"rowLayout\":[{}],\"initial\":{},\"steps\":[",
+           algorithm.getName(),
+           limit.getName(),
+           partial.len() - 1,
+           statsToJson(stats),
+           rowLayout.iter().map(|row| row.to_string()).collect::<Vec<_>>().join(","),
+           bottlesToJson(&partial[0])).unwrap();
+    let boards: Vec<&[Bottle]>= partial.iter().map(Vec::as_slice).collect();
+    writeSteps(&mut out, &boards);
+    out.push_str("]}");
+    out
+}
+
+/// Writes every board after the first one as a step with the pour leading to it
+fn writeSteps(out: &mut String, boards: &[&[Bottle]]) {
+    for (i, pair) in boards.windows(2).enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let pour= Pour::between(pair[0], pair[1]).expect("Consecutive positions must be one pour apart");
+        write!(out, "{{\"step\":{},\"move\":{},\"bottles\":{}}}", i + 1, pourToJson(&pour), bottlesToJson(pair[1])).unwrap();
+    }
+}
+
+/// Serializes the outcome of a search that found no solution
+pub fn noSolutionToJson(algorithm: Algorithm, stats: &SearchStats) -> String {
+    format!("{{\"solved\":false,\"algorithm\":\"{}\",\"stats\":{}}}", algorithm.getName(), statsToJson(stats))
+}
+
+/// Serializes a hint: the next pour (`null` if the position is already solved), the number of moves
+/// left in an optimal continuation and search statistics
+pub fn hintToJson(hint: &Hint, algorithm: Algorithm) -> String {
+    format!("{{\"solved\":true,\"algorithm\":\"{}\",\"move\":{},\"remainingMoves\":{},\"stats\":{}}}",
+            algorithm.getName(),
+            hint.pour.as_ref().map_or("null".to_string(), pourToJson),
+            hint.remainingMoves,
+            statsToJson(&hint.stats))
+}
+
+fn statsToJson(stats: &SearchStats) -> String {
+    format!("{{\"expanded\":{},\"generated\":{},\"duplicates\":{},\"peakFrontier\":{},\"peakMemoryBytes\":{},\"elapsedMs\":{:.3}}}",
+            stats.expanded,
+            stats.generated,
+            stats.duplicates,
+            stats.peakFrontier,
+            stats.peakMemory,
+            stats.elapsed.as_secs_f64() * 1000.0)
+}
+
+fn pourToJson(pour: &Pour) -> String {
+    format!("{{\"from\":{},\"to\":{},\"color\":{},\"amount\":{}}}",
+            pour.from + 1,
+            pour.to + 1,
+            quote(&(pour.color as char).to_string()),
+            pour.amount)
+}
+
+fn bottlesToJson(bottles: &[Bottle]) -> String {
+    let strings: Vec<String>= bottlesToText(bottles).iter().map(|text| quote(text)).collect();
+    format!("[{}]", strings.join(","))
+}
+
+/// Wraps a string in quotes, escaping the characters JSON does not allow verbatim
+fn quote(text: &str) -> String {
+    let mut out= String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            ch if (ch as u32) < 0x20 => write!(out, "\\u{:04x}", ch as u32).unwrap(),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn quoteEscapes() {
+        assert_eq!(quote("AB"), "\"AB\"");
+        assert_eq!(quote("\"\\"), "\"\\\"\\\\\"");
+        assert_eq!(quote("\t"), "\"\\u0009\"");
+    }
+
+    #[test]
+    fn solutionJson() {
+        let puzzle= Puzzle::parse("AB;BA;", None).unwrap();
+        let solution= solve(&puzzle, &SolveOptions::default()).unwrap();
+        let json= solutionToJson(&solution, &puzzle.rowLayout);
+        assert!(json.starts_with("{\"solved\":true,\"algorithm\":\"astar\",\"moveCount\":3,\"lowerBound\":3,\"stats\":{\"expanded\":"));
+        assert!(json.contains("\"rowLayout\":[3],\"initial\":[\"AB\",\"BA\",\"\"],\"steps\":[{\"step\":1,\"move\":{\"from\":"));
+        assert_eq!(json.matches("\"step\":").count(), 3);
+        assert!(json.ends_with("]}]}"));
+    }
+
+    #[test]
+    fn bottleSizesAndLocksJson() {
+        let puzzle= Puzzle::parse("AAB/3;BA;", None).unwrap();
+        let solution= solve(&puzzle, &SolveOptions::default()).unwrap();
+        let json= solutionToJson(&solution, &puzzle.rowLayout);
+        assert!(json.contains("\"initial\":[\"AAB/3\",\"BA/2\",\"/2\"]"));
+        // Locks count down from step to step
+        let puzzle= Puzzle::parse("AB;BA@2;;", None).unwrap();
+        let json= solutionToJson(&solve(&puzzle, &SolveOptions::default()).unwrap(), &puzzle.rowLayout);
+        assert!(json.contains("\"initial\":[\"AB\",\"BA@2\",\"\",\"\"]"));
+        assert!(json.contains("\"step\":1,") && json.contains("\"BA@1\""));
+    }
+
+    #[test]
+    fn extraTubesJson() {
+        let puzzle= Puzzle::parse("AB;BA", None).unwrap();
+        let extraTubes= solveWithExtraTubes(&puzzle, &SolveOptions::default(), 1).unwrap();
+        let json= extraTubesToJson(&extraTubes);
+        assert!(json.contains("\"lowerBound\":3,\"extraTubes\":[{\"bottle\":3,\"beforeStep\":1}],\"stats\":"));
+        assert!(json.contains("\"rowLayout\":[2,1],\"initial\":[\"AB\",\"BA\",\"\"]"));
+    }
+
+    #[test]
+    fn budgetExceededJson() {
+        let puzzle= Puzzle::parse("AB;BA;", None).unwrap();
+        let options= SolveOptions { limits: SearchLimits { maxExpanded: Some(1), ..SearchLimits::default() }, ..SolveOptions::default() };
+        let Err(SolveError::BudgetExceeded { limit, partial, stats })= solve(&puzzle, &options) else {
+            panic!("The search should run out of budget");
+        };
+        let json= budgetExceededToJson(Algorithm::Astar, limit, &partial, &stats, &puzzle.rowLayout);
+        assert!(json.starts_with(&format!("{{\"solved\":false,\"algorithm\":\"astar\",\"budgetExceeded\":\"nodes\",\"partialMoveCount\":{},", partial.len() - 1)));
+        assert_eq!(json.matches("\"step\":").count(), partial.len() - 1);
+    }
+}
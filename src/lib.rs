@@ -0,0 +1,39 @@
+#![allow(non_snake_case, clippy::module_inception, clippy::upper_case_acronyms)]
+//! A memory optimized A* and BFS based solver for liquid sort puzzles.
+//!
+//! The easiest way in is [`solve`], which takes a parsed [`Puzzle`] and returns the shortest
+//! possible sequence of positions leading to the solved state:
+//!
+//! ```
+//! use liquid_sort_solver::{solve, Puzzle, SolveOptions};
+//!
+//! let puzzle= Puzzle::parse("ABA;BAB;;", None).unwrap();
+//! let solution= solve(&puzzle, &SolveOptions::default()).unwrap();
+//! assert_eq!(solution.moveCount(), 5);
+//! ```
+pub mod bottle;
+pub mod bfs;
+pub mod astar;
+pub mod idastar;
+pub mod traits;
+pub mod puzzle;
+pub mod pour;
+pub mod json;
+pub mod mystery;
+pub mod walkthrough;
+pub mod generator;
+pub mod verify;
+pub mod batch;
+pub mod solver;
+pub mod stats;
+pub mod budget;
+pub mod identity;
+pub mod rules;
+
+pub use bottle::{Bottle, Lock};
+pub use astar::position_astar::Heuristic;
+pub use puzzle::Puzzle;
+pub use pour::Pour;
+pub use rules::{PourRule, Rules};
+pub use solver::{hint, solve, solveAnytime, solveWithExtraTubes, Algorithm, ExtraTubes, Hint, Solution, SolveError, SolveOptions};
+pub use stats::SearchStats;
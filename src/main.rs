@@ -1,21 +1,46 @@
 #![allow(non_snake_case)]
-mod bottle;
-mod bfs;
-mod astar;
-mod traits;
-
+use std::fs;
+use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read};
+use std::fmt;
 use std::fmt::Write;
 use std::process;
 use std::rc::Rc;
-use clap::{Arg, ArgAction, Command};
-use bottle::*;
-use bfs::position_bfs::*;
-use astar::position_astar::*;
-use bfs::bfs::*;
-use astar::astar::*;
-use traits::position::*;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use liquid_sort_solver::*;
+use liquid_sort_solver::astar::position_astar::*;
+use liquid_sort_solver::budget::*;
+use liquid_sort_solver::traits::position::*;
+
+/// The file given with --output. Without it the results go to standard output
+static OUTPUT: OnceLock<Mutex<File>>= OnceLock::new();
+
+/// Writes results to the --output file or to standard output, exiting if that fails
+fn writeOutput(text: fmt::Arguments) {
+    let result= match OUTPUT.get() {
+        Some(file) => io::Write::write_fmt(&mut *file.lock().unwrap(), text),
+        None => io::Write::write_fmt(&mut io::stdout(), text),
+    };
+    if let Err(error)= result {
+        eprintln!("Error: Failed to write the output: {}", error);
+        process::exit(1);
+    }
+}
+
+/// Like `print!`, but honoring --output
+macro_rules! output {
+    ($($arg:tt)*) => { writeOutput(format_args!($($arg)*)) };
+}
+
+/// Like `println!`, but honoring --output
+macro_rules! outputln {
+    () => { writeOutput(format_args!("\n")) };
+    ($($arg:tt)*) => { writeOutput(format_args!("{}\n", format_args!($($arg)*))) };
+}
 
 fn formatPosition(position: &Rc<dyn Position>, possiblePrevious: &Option<Rc<dyn Position>>, row_layout: &[usize]) -> Result<String, String> {
     let mut out= String::new();
@@ -31,11 +56,17 @@ fn formatPosition(position: &Rc<dyn Position>, possiblePrevious: &Option<Rc<dyn
     }
     let mut row_start = 0;
     for (row_index, row_len) in row_layout.iter().enumerate() {
-        for i in (0..4).rev() {
-            for j in 0..*row_len {
+        let row= &bottles[row_start..row_start + *row_len];
+        let height= row.iter().map(|bottle| bottle.getCapacity()).max().unwrap_or(0);
+        for i in (0..height).rev() {
+            for (j, ourBottle) in row.iter().enumerate() {
                 let index = row_start + j;
-                let ourBottle= &bottles[index];
-                if let Some(_) = possiblePrevious.as_ref().filter(|p| ourBottle != &p.getBottles()[index]) {
+                // Shorter bottles have no slots up here
+                if i >= ourBottle.getCapacity() {
+                    out.push_str("   ");
+                    continue;
+                }
+                if possiblePrevious.as_ref().is_some_and(|p| ourBottle.getContent() != p.getBottles()[index].getContent()) {
                     write!(out, "❚{}❚", ourBottle.content[i] as char).unwrap();
                 } else {
                     write!(out, "|{}|", ourBottle.content[i] as char).unwrap();
@@ -43,6 +74,13 @@ fn formatPosition(position: &Rc<dyn Position>, possiblePrevious: &Option<Rc<dyn
             }
             writeln!(out).unwrap();
         }
+        // Locks under their bottles, e.g. "@3"
+        if row.iter().any(Bottle::isLocked) {
+            for bottle in row {
+                write!(out, "{:^3}", bottle.lock).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
         row_start += *row_len;
         if row_index + 1 < row_layout.len() {
             writeln!(out).unwrap();
@@ -52,122 +90,664 @@ fn formatPosition(position: &Rc<dyn Position>, possiblePrevious: &Option<Rc<dyn
     Ok(out)
 }
 
-fn printSolution(possibleSolution: Option<Vec<Rc<dyn Position>>>, row_layout: &[usize]) {
-    if let Some(solution) = possibleSolution {
-        let mut oldPosition: Option<Rc<dyn Position>>= None;
-        for i in 0..solution.len() {
-            println!("Step {}\n{}", i, formatPosition(&solution[i], &oldPosition, row_layout).unwrap());
-            oldPosition= Some(solution[i].clone());
+fn printSolution(solution: &Solution, row_layout: &[usize]) {
+    printSteps(&solution.positions, 0, row_layout);
+}
+
+/// Prints consecutive positions as numbered steps, each headed by the pour that produced it
+fn printSteps(positions: &[Rc<dyn Position>], firstStep: usize, row_layout: &[usize]) {
+    let pours= Pour::fromPositions(positions);
+    let mut oldPosition: Option<Rc<dyn Position>>= None;
+    for (i, position) in positions.iter().enumerate() {
+        if i > 0 {
+            outputln!("Step {}: {}\n{}", firstStep + i, pours[i - 1], formatPosition(position, &oldPosition, row_layout).unwrap());
+        } else if firstStep == 0 {
+            outputln!("Step {}\n{}", i, formatPosition(position, &oldPosition, row_layout).unwrap());
+        }
+        oldPosition= Some(position.clone());
+    }
+}
+
+fn printMoves(solution: &Solution) {
+    printPours(&solution.positions, 0);
+}
+
+fn printPours(positions: &[Rc<dyn Position>], firstStep: usize) {
+    for (i, pour) in Pour::fromPositions(positions).iter().enumerate() {
+        outputln!("{}. {}", firstStep + i + 1, pour);
+    }
+}
+
+/// Reports a search stopped by a limit along with the most promising partial progress, then exits
+/// with 3 to tell it apart from a puzzle without solution
+fn reportBudgetExceeded(algorithm: Algorithm, limit: Limit, partial: &[Vec<Bottle>], stats: &SearchStats, row_layout: &[usize], json: bool, movesOnly: bool) -> ! {
+    if json {
+        outputln!("{}", json::budgetExceededToJson(algorithm, limit, partial, stats, row_layout));
+    } else {
+        outputln!("The {} limit was exceeded after expanding {} positions in {:.3}s. Best partial progress ({} moves):",
+                 limit, stats.expanded, stats.elapsed.as_secs_f64(), partial.len() - 1);
+        let positions: Vec<Rc<dyn Position>>= partial
+            .iter()
+            .map(|bottles| Rc::new(PositionAstar::new(bottles.clone())) as Rc<dyn Position>)
+            .collect();
+        if movesOnly {
+            printPours(&positions, 0);
+        } else {
+            printSteps(&positions, 0, row_layout);
         }
+    }
+    process::exit(3);
+}
+
+/// Prints a solution found by the anytime search before it finishes: the pours in text mode, or a
+/// JSON object on a line of its own
+fn reportImprovement(solution: &Solution, row_layout: &[usize], json: bool) {
+    if json {
+        outputln!("{}", json::solutionToJson(solution, row_layout));
     } else {
-        println!("No solution was found");
+        outputln!("Found a solution with {} moves after {:.3}s, at least {} moves are needed:",
+                 solution.moveCount(), solution.stats.elapsed.as_secs_f64(), solution.lowerBound);
+        printMoves(solution);
     }
 }
 
-/// Reads and processes input data from standard input to initialize and execute a BFS (Breadth-First Search)
-/// based solution finding process for a liquid sort puzzle
-///
-/// # Operation
-/// - Reads lines of input where each line represents a row of bottle configurations separated by ';'.
-/// - Each bottle can contain up to four ASCII characters representing its content; shorter entries are padded with spaces.
-/// - Lines starting with '#' are considered as comments and are ignored.
-/// - Lines that are entirely whitespace are also ignored.
-/// - Validates that all characters in each bottle are ASCII and each bottle has at most four characters.
-///
-/// # Returns
-/// - `Ok((Vec<Bottle>, Vec<usize>))` containing bottle data and row layout if the input is successfully read and validated.
-/// - `Err(String)` if there are any input errors like non-ASCII characters, incorrect bottle lengths,
-///   or if any other runtime error occurs during processing.
-///
-/// # Errors
-/// - Returns `Err(String)` and prints an error message if any line contains non-ASCII characters.
-/// - Returns `Err(String)` and prints an error message if any bottle contains more than four characters.
-///
-/// # Example Usage
-/// This function is intended to be called at the start of the program to handle the setup of game state
-/// from standard input and to kick off the solution process.
-///
-/// ```
-/// fn main() {
-///     if let Err(_) = handleInputData() {
-///         eprintln!("Failed to process input data.");
-///     }
-/// }
-/// ```
-fn handleInputData() -> Result<(Vec<Bottle>, Vec<usize>), String> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input).expect("Failed to read input");
-    let mut data: Vec<Bottle>= Vec::new();
-    let mut row_layout: Vec<usize>= Vec::new();
-    // Read each line from standard input
-    for raw_line in input.lines() {
-        let line = raw_line.trim_end_matches('\r');
-        if line.trim().is_empty() {
-            continue;
+/// Prints the counters of the search to standard error, so they never mix with the solution
+fn reportStats(result: Result<SearchStats, &SolveError>, enabled: bool) {
+    if let (true, Some(stats))= (enabled, result.map_or_else(SolveError::getStats, Some)) {
+        eprintln!("Statistics: {}", stats);
+    }
+}
+
+/// Prints an error ending the search and exits with 1. Errors in the puzzle name the input it was read from
+fn exitWithError(error: &SolveError, path: &str) -> ! {
+    match error {
+        SolveError::InvalidPuzzle(_) => eprintln!("{}: {}", getInputName(path), error),
+        _ => eprintln!("{}", error),
+    }
+    process::exit(1);
+}
+
+/// Collects the search settings given on the command line, exiting on invalid values
+fn getSolveOptions(matches: &ArgMatches) -> SolveOptions {
+    let algorithm= if matches.get_flag("bfs") {
+        Algorithm::BFS
+    } else if matches.get_flag("bidirectional") {
+        Algorithm::Bidirectional
+    } else if matches.get_flag("idastar") {
+        Algorithm::IDAstar
+    } else {
+        Algorithm::Astar
+    };
+    let threads= match matches.get_one::<usize>("threads").copied().unwrap() {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+    let progress= Duration::try_from_secs_f64(*matches.get_one::<f64>("progress").unwrap()).unwrap_or_else(|_| {
+        eprintln!("Error: The progress interval must be a number of seconds");
+        process::exit(1);
+    });
+    let options= SolveOptions { algorithm,
+                                limits: getLimits(matches),
+                                threads,
+                                transpositionTable: *matches.get_one::<usize>("transposition-table").unwrap(),
+                                weight: *matches.get_one::<f64>("weight").unwrap(),
+                                heuristic: Heuristic::fromName(matches.get_one::<String>("heuristic").unwrap()).unwrap(),
+                                rules: Rules { pruning: !matches.get_flag("no-pruning"),
+                                               pour: PourRule::fromName(matches.get_one::<String>("pour").unwrap()).unwrap() },
+                                progress: if matches.get_flag("quiet") { None } else { Some(progress) } };
+    if options.weight < 1.0 {
+        eprintln!("Error: The weight must be at least 1");
+        process::exit(1);
+    }
+    options
+}
+
+/// Handles `--batch`: solves every puzzle of the given files, or of standard input, and prints a
+/// summary table. Exits with 0 if all of them were solved, 2 if any was not, and 1 if a file cannot
+/// be read
+fn runBatch(matches: &ArgMatches, options: &SolveOptions) {
+    let files: Vec<String>= matches.get_many::<String>("files").map_or_else(|| vec![ "-".to_string() ], |files| files.cloned().collect());
+    let mut entries= Vec::new();
+    for file in &files {
+        let text= readInput(file).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        entries.extend(batch::splitPuzzles(getInputName(file), &text));
+    }
+    let jobs= match matches.get_one::<usize>("jobs").copied().unwrap() {
+        0 => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        jobs => jobs,
+    };
+    // Progress lines of puzzles solved at the same time would only be confusing
+    let options= SolveOptions { progress: None, ..options.clone() };
+    let start= Instant::now();
+    let reports= batch::solveBatch(&entries, matches.get_one::<usize>("capacity").copied(), &options, jobs);
+    for report in &reports {
+        if let batch::BatchStatus::Invalid(error)= &report.status {
+            eprintln!("{}: {}", report.name, error);
         }
-        if line.trim_start().starts_with('#') {
-            continue;
+    }
+    output!("{}", batch::formatSummary(&reports, start.elapsed()));
+    if !reports.iter().all(|report| matches!(report.status, batch::BatchStatus::Solved { .. })) {
+        process::exit(2);
+    }
+}
+
+/// Handles `--extra-tubes`: solves the puzzle with as few extra empty bottles as possible, telling
+/// how many are needed and before which step to add each of them, then prints the solution
+fn runExtraTubes(puzzle: &Puzzle, options: &SolveOptions, maxTubes: usize, matches: &ArgMatches, path: &str) {
+    let json= matches.get_one::<String>("format").unwrap() == "json";
+    let result= solveWithExtraTubes(puzzle, options, maxTubes);
+    reportStats(result.as_ref().map(|extraTubes| extraTubes.solution.stats), matches.get_flag("stats"));
+    match result {
+        Ok(extraTubes) if json => outputln!("{}", json::extraTubesToJson(&extraTubes)),
+        Ok(extraTubes) => {
+            let count= extraTubes.count();
+            if count == 0 {
+                outputln!("Solvable without extra tubes\n");
+            } else {
+                outputln!("Solvable only with {} extra tube{}:", count, if count == 1 { "" } else { "s" });
+                for (i, step) in extraTubes.addBefore.iter().enumerate() {
+                    outputln!("Add bottle {} before step {}", puzzle.bottles.len() + i + 1, step);
+                }
+                outputln!();
+            }
+            let solution= &extraTubes.solution;
+            if matches.get_flag("step") {
+                if let Err(error) = stepThrough(solution, &extraTubes.puzzle.rowLayout) {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+            } else if matches.get_flag("moves") {
+                printMoves(solution);
+            } else {
+                printSolution(solution, &extraTubes.puzzle.rowLayout);
+            }
+            if solution.lowerBound < solution.moveCount() {
+                outputln!("This solution may not be the shortest: at least {} moves are needed", solution.lowerBound);
+            }
+        },
+        Err(SolveError::NoSolution { stats }) if json => outputln!("{}", json::noSolutionToJson(options.algorithm, &stats)),
+        Err(SolveError::NoSolution { .. }) =>
+            outputln!("No solution was found, even with {} extra tube{}", maxTubes, if maxTubes == 1 { "" } else { "s" }),
+        Err(SolveError::BudgetExceeded { limit, partial, stats }) => {
+            // The search was cut short with some of the tubes added
+            let rowLayout= puzzle.withExtraTubes(partial[0].len() - puzzle.bottles.len()).rowLayout;
+            reportBudgetExceeded(options.algorithm, limit, &partial, &stats, &rowLayout, json, matches.get_flag("moves"))
+        },
+        Err(error) => exitWithError(&error, path),
+    }
+}
+
+/// Collects the search limits given on the command line
+fn getLimits(matches: &ArgMatches) -> SearchLimits {
+    SearchLimits {
+        maxExpanded: matches.get_one::<u64>("max-nodes").copied(),
+        maxMemory: matches.get_one::<usize>("max-memory").map(|mebibytes| mebibytes << 20),
+        timeout: matches.get_one::<f64>("timeout").map(|seconds| Duration::from_secs_f64(*seconds)),
+    }
+}
+
+#[cfg(windows)]
+const TERMINAL: &str = "CONIN$";
+#[cfg(not(windows))]
+const TERMINAL: &str = "/dev/tty";
+
+/// Asks the user on the terminal which color a pour has uncovered. The puzzle itself is read from
+/// standard input, so the answer is read from the terminal device instead.
+fn askRevealedColor(bottleIndex: usize) -> Result<u8, String> {
+    let terminal= File::open(TERMINAL)
+        .map_err(|error| format!("Error: Hidden slots need a terminal to ask for revealed colors: {}", error))?;
+    let mut reader= BufReader::new(terminal);
+    loop {
+        eprint!("Bottle {} uncovered a hidden slot. Which color is it? ", bottleIndex + 1);
+        let mut answer= String::new();
+        if reader.read_line(&mut answer).map_err(|error| format!("Error: Failed to read answer: {}", error))? == 0 {
+            return Err("Error: No color was given for the hidden slot".to_string());
+        }
+        let answer= answer.trim();
+        match answer.as_bytes() {
+            [color] if color.is_ascii_graphic() && *color != bottle::UNKNOWN => return Ok(*color),
+            _ => eprintln!("Please type a single character other than '{}'", bottle::UNKNOWN as char),
         }
-        let mut row_count = 0;
-        for bottle in line.split(';') {
-            if !bottle.is_ascii() {
-                return Err("Error: All characters must be ASCII".to_string());
+    }
+}
+
+/// Lets the user walk through the solution interactively, reading keys from the terminal
+fn stepThrough(solution: &Solution, row_layout: &[usize]) -> Result<(), String> {
+    let terminal= File::open(TERMINAL)
+        .map_err(|error| format!("Error: Stepping through a solution needs a terminal: {}", error))?;
+    walkthrough::walkThrough(solution, row_layout, BufReader::new(terminal), io::stdout())
+        .map_err(|error| format!("Error: {}", error))
+}
+
+/// Solves a puzzle with hidden slots, printing every planned stretch of moves before asking for the
+/// color uncovered at its end
+fn solveInteractively(puzzle: &Puzzle, options: &SolveOptions, json: bool, movesOnly: bool) -> Result<Solution, SolveError> {
+    let mut printedSteps= 0;
+    let solution= mystery::solveMystery(puzzle, options, |stage, bottleIndex| {
+        if !json {
+            if movesOnly {
+                printPours(stage, printedSteps);
+            } else {
+                printSteps(stage, printedSteps, &puzzle.rowLayout);
             }
-            if bottle.len() > 4 {
-                return Err("Error: Each bottle must contain at most four characters.".to_string());
+        }
+        printedSteps += stage.len() - 1;
+        askRevealedColor(bottleIndex)
+    })?;
+    if !json {
+        let finalStage= &solution.positions[printedSteps..];
+        if movesOnly {
+            printPours(finalStage, printedSteps);
+        } else {
+            printSteps(finalStage, printedSteps, &puzzle.rowLayout);
+        }
+    }
+    Ok(solution)
+}
+
+/// Reads the contents of a puzzle file, or of standard input for `-`
+fn readInput(path: &str) -> Result<String, String> {
+    let text= if path == "-" {
+        let mut input= String::new();
+        io::stdin().read_to_string(&mut input).map(|_| input)
+    } else {
+        fs::read_to_string(path)
+    };
+    text.map_err(|error| format!("Error: Failed to read {}: {}", getInputName(path), error))
+}
+
+/// The name of an input in messages: the path of a file, or "stdin" for `-`
+fn getInputName(path: &str) -> &str {
+    if path == "-" { "stdin" } else { path }
+}
+
+/// Reads the puzzle from a file or from standard input.
+///
+/// # Arguments
+/// * `path` - The puzzle file, or `-` for standard input.
+/// * `capacity` - The number of slots in every bottle, if known upfront.
+///
+/// # Returns
+/// - `Ok(Puzzle)` if the input is successfully read and validated.
+/// - `Err(String)` if the input cannot be read or parsed, prefixed with the name of the input.
+fn handleInputData(path: &str, capacity: Option<usize>) -> Result<Puzzle, String> {
+    let input= readInput(path)?;
+    Puzzle::parse(&input, capacity).map_err(|error| format!("{}: {}", getInputName(path), error))
+}
+
+/// Handles the `generate` subcommand: prints a random puzzle preceded by a comment describing how
+/// it was made, so it can be reproduced
+fn runGenerate(matches: &ArgMatches) {
+    let seed= matches.get_one::<u64>("seed").copied().unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(0)
+    });
+    let options= generator::GeneratorOptions {
+        colors: *matches.get_one::<usize>("colors").unwrap(),
+        emptyBottles: *matches.get_one::<usize>("empty").unwrap(),
+        capacity: *matches.get_one::<usize>("capacity").unwrap(),
+        seed,
+        solvable: matches.get_flag("solvable"),
+        targetMoves: matches.get_one::<usize>("moves").copied(),
+        maxAttempts: *matches.get_one::<usize>("attempts").unwrap(),
+    };
+    match generator::generate(&options) {
+        Ok(generated) => {
+            output!("# {} colors, {} empty bottles, capacity {}, seed {}", options.colors, options.emptyBottles, options.capacity, seed);
+            if let Some(moves) = generated.optimalMoves {
+                output!(", shortest solution {} moves", moves);
             }
-            let paddedBottle= format!("{:width$}", bottle, width = 4);
-            let array: [u8; 4] = paddedBottle.as_bytes().to_vec().try_into().unwrap();
-            data.push(Bottle::new(array));
-            row_count += 1;
+            outputln!();
+            output!("{}", generated.puzzle.toText());
+        },
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
         }
-        row_layout.push(row_count);
     }
-    if data.is_empty() {
-        return Err("Error: No bottle data found.".to_string());
+}
+
+/// Handles the `verify` subcommand. Exits with 0 if the moves solve the puzzle, 2 if a move is
+/// illegal or the puzzle is left unsolved, and 1 on input errors
+fn runVerify(matches: &ArgMatches) {
+    let movesPath= matches.get_one::<String>("moves").unwrap();
+    let path= matches.get_one::<String>("file").map_or("-", |path| path.as_str());
+    let result= fs::read_to_string(movesPath)
+        .map_err(|error| format!("Error: Failed to read {}: {}", movesPath, error))
+        .and_then(|text| verify::parseMoves(&text))
+        .and_then(|pours| Ok((handleInputData(path, matches.get_one::<usize>("capacity").copied())?, pours)));
+    let (puzzle, pours)= result.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    let rules= Rules { pour: PourRule::fromName(matches.get_one::<String>("pour").unwrap()).unwrap(), ..Rules::default() };
+    match verify::verify(&puzzle, &pours, &rules) {
+        verify::Verdict::Solved => outputln!("All {} moves are legal and the puzzle is solved", pours.len()),
+        verify::Verdict::Unsolved(bottles) => {
+            let position: Rc<dyn Position>= Rc::new(PositionAstar::new(bottles));
+            outputln!("All {} moves are legal, but the puzzle is not solved:\n{}", pours.len(),
+                      formatPosition(&position, &None, &puzzle.rowLayout).unwrap());
+            process::exit(2);
+        },
+        verify::Verdict::Illegal { step, pour, reason } => {
+            outputln!("Step {} (line {}) is illegal: pour {} → {}: {}", step, pour.line, pour.from + 1, pour.to + 1, reason);
+            process::exit(2);
+        },
     }
-    Ok((data, row_layout))
-} 
+}
 
 fn main() {
     let matches = Command::new("Bottle Sort Puzzle Solver")
     .version("1.0")
     .author("Volodymyr Kleban")
     .about("Uses an algorithm based on command line arguments to sort liquids in bottles")
-    .override_usage("liquid_sort_solver [OPTIONS] <puzzle data")
+    .override_usage("liquid_sort_solver [OPTIONS] [FILE]\n       liquid_sort_solver --batch [OPTIONS] [FILES]...\n       liquid_sort_solver generate [OPTIONS] --colors <COLORS>\n       liquid_sort_solver verify [OPTIONS] <MOVES> [FILE]")
     .arg(Arg::new("bfs")
          .long("bfs")
          .action(ArgAction::SetTrue)
          .help("Use the BFS algorithm"))
+    .arg(Arg::new("bidirectional")
+         .long("bidirectional")
+         .action(ArgAction::SetTrue)
+         .help("Use BFS from both the puzzle and the solved position, meeting in the middle"))
     .arg(Arg::new("astar")
          .long("astar")
          .action(ArgAction::SetTrue)
          .help("Use the A* algorithm (default)"))
+    .arg(Arg::new("weight")
+         .long("weight")
+         .value_name("W")
+         .value_parser(value_parser!(f64))
+         .default_value("1")
+         .conflicts_with_all(["bfs", "bidirectional", "idastar"])
+         .help("Weigh the A* heuristic by W: faster, but the solution may be up to W times longer than the shortest"))
+    .arg(Arg::new("anytime")
+         .long("anytime")
+         .action(ArgAction::SetTrue)
+         .conflicts_with_all(["bfs", "bidirectional", "idastar"])
+         .help("Print a quick A* solution (weight 5 unless --weight is given), then shorter ones until the shortest is proven"))
+    .arg(Arg::new("idastar")
+         .long("idastar")
+         .action(ArgAction::SetTrue)
+         .help("Use the IDA* algorithm, which needs little memory but revisits positions"))
+    .arg(Arg::new("transposition-table")
+         .long("transposition-table")
+         .value_name("ENTRIES")
+         .value_parser(value_parser!(usize))
+         .default_value("0")
+         .help("Let IDA* remember up to this many positions to skip repeated work"))
+    .arg(Arg::new("heuristic")
+         .long("heuristic")
+         .value_name("NAME")
+         .value_parser(Heuristic::ALL.map(|heuristic| heuristic.getName()))
+         .default_value("towers")
+         .help("Estimate of the moves left guiding A* and IDA*; stronger ones expand fewer positions but cost more each"))
+    .arg(Arg::new("pour")
+         .long("pour")
+         .value_name("RULE")
+         .value_parser(PourRule::ALL.map(|rule| rule.getName()))
+         .default_value("run")
+         .help("How much a pour moves: the run of the top color as far as it fits, a single unit, or the whole run only if all of it fits"))
+    .arg(Arg::new("extra-tubes")
+         .long("extra-tubes")
+         .value_name("COUNT")
+         .value_parser(value_parser!(usize))
+         .conflicts_with_all(["anytime", "hint", "batch"])
+         .help("Allow adding up to COUNT extra empty bottles, report the fewest needed and before which step to add each"))
+    .arg(Arg::new("no-pruning")
+         .long("no-pruning")
+         .action(ArgAction::SetTrue)
+         .help("Also make the pours that never shorten a solution, for comparison"))
+    .arg(Arg::new("format")
+         .long("format")
+         .value_name("FORMAT")
+         .value_parser(["text", "json"])
+         .default_value("text")
+         .help("Print the solution as human readable text or as JSON"))
+    .arg(Arg::new("output")
+         .long("output")
+         .value_name("PATH")
+         .global(true)
+         .help("Write the results to this file instead of standard output"))
+    .arg(Arg::new("moves")
+         .long("moves")
+         .action(ArgAction::SetTrue)
+         .help("Print only the list of pours instead of every board"))
+    .arg(Arg::new("hint")
+         .long("hint")
+         .action(ArgAction::SetTrue)
+         .help("Print only the next pour of an optimal solution and how many moves remain"))
+    .arg(Arg::new("step")
+         .long("step")
+         .action(ArgAction::SetTrue)
+         .conflicts_with("output")
+         .help("Walk through the solution one board at a time on the terminal"))
+    .arg(Arg::new("capacity")
+         .long("capacity")
+         .value_name("SLOTS")
+         .value_parser(value_parser!(usize))
+         .help("Number of slots in each bottle without a /SIZE suffix (default: the longest such bottle in the input)"))
+    .arg(Arg::new("max-nodes")
+         .long("max-nodes")
+         .value_name("COUNT")
+         .value_parser(value_parser!(u64))
+         .help("Stop the search after expanding this many positions"))
+    .arg(Arg::new("max-memory")
+         .long("max-memory")
+         .value_name("MIB")
+         .value_parser(value_parser!(usize))
+         .help("Stop the search once it holds about this many MiB of positions"))
+    .arg(Arg::new("timeout")
+         .long("timeout")
+         .value_name("SECONDS")
+         .value_parser(value_parser!(f64))
+         .help("Stop the search after this many seconds"))
+    .arg(Arg::new("threads")
+         .long("threads")
+         .value_name("COUNT")
+         .value_parser(value_parser!(usize))
+         .default_value("1")
+         .help("Expand every BFS move on this many threads, 0 for one per core. A* always uses one thread"))
+    .arg(Arg::new("progress")
+         .long("progress")
+         .value_name("SECONDS")
+         .value_parser(value_parser!(f64))
+         .default_value("1")
+         .help("Print the search statistics to standard error this often while searching"))
+    .arg(Arg::new("quiet")
+         .long("quiet")
+         .action(ArgAction::SetTrue)
+         .help("Print nothing to standard error while searching"))
+    .arg(Arg::new("stats")
+         .long("stats")
+         .action(ArgAction::SetTrue)
+         .help("Print the search statistics to standard error when the search ends"))
+    .arg(Arg::new("batch")
+         .long("batch")
+         .action(ArgAction::SetTrue)
+         .help(format!("Solve every puzzle in FILES, or in standard input, and print a summary table. Puzzles in one file are separated by lines starting with {}", batch::DELIMITER)))
+    .arg(Arg::new("jobs")
+         .long("jobs")
+         .value_name("COUNT")
+         .value_parser(value_parser!(usize))
+         .default_value("1")
+         .help("Solve this many puzzles of a batch at the same time, 0 for one per core"))
+    .arg(Arg::new("files")
+         .value_name("FILES")
+         .num_args(1..)
+         .help("Puzzle file, - or none for standard input. --batch takes several"))
+    .subcommand(Command::new("generate")
+         .about("Generates a random valid puzzle in the input format")
+         .arg(Arg::new("colors")
+              .long("colors")
+              .value_name("COLORS")
+              .required(true)
+              .value_parser(value_parser!(usize))
+              .help("Number of colors, each filling one bottle"))
+         .arg(Arg::new("empty")
+              .long("empty")
+              .value_name("BOTTLES")
+              .default_value("2")
+              .value_parser(value_parser!(usize))
+              .help("Number of empty bottles"))
+         .arg(Arg::new("capacity")
+              .long("capacity")
+              .value_name("SLOTS")
+              .default_value("4")
+              .value_parser(value_parser!(usize))
+              .help("Number of slots in each bottle"))
+         .arg(Arg::new("seed")
+              .long("seed")
+              .value_name("SEED")
+              .value_parser(value_parser!(u64))
+              .help("Seed of the random generator (default: derived from the clock)"))
+         .arg(Arg::new("solvable")
+              .long("solvable")
+              .action(ArgAction::SetTrue)
+              .help("Only output a puzzle that has a solution"))
+         .arg(Arg::new("moves")
+              .long("moves")
+              .value_name("MOVES")
+              .value_parser(value_parser!(usize))
+              .help("Only output a puzzle whose shortest solution has exactly this many moves"))
+         .arg(Arg::new("attempts")
+              .long("attempts")
+              .value_name("COUNT")
+              .default_value("1000")
+              .value_parser(value_parser!(usize))
+              .help("How many candidates to try for --solvable or --moves")))
+    .subcommand(Command::new("verify")
+         .about("Replays a move list against a puzzle")
+         .arg(Arg::new("moves")
+              .value_name("MOVES")
+              .required(true)
+              .help("File with one pour per line, e.g. \"pour 3 → 17\" or \"3 17\""))
+         .arg(Arg::new("file")
+              .value_name("FILE")
+              .help("Puzzle file, - or none for standard input"))
+         .arg(Arg::new("pour")
+              .long("pour")
+              .value_name("RULE")
+              .value_parser(PourRule::ALL.map(|rule| rule.getName()))
+              .default_value("run")
+              .help("How much a pour moves, as for solving"))
+         .arg(Arg::new("capacity")
+              .long("capacity")
+              .value_name("SLOTS")
+              .value_parser(value_parser!(usize))
+              .help("Number of slots in each bottle without a /SIZE suffix (default: the longest such bottle in the input)")))
     .get_matches();
 
-    match handleInputData() {
-        Ok((data, row_layout)) => {
-            if matches.get_flag("bfs") {
-                let position= PositionBFS::new(data, 0);
-                if let Some(error) = position.isValid().err() {
+    // --output may be given before or after a subcommand
+    let subcommandMatches= matches.subcommand().map_or(&matches, |(_, subcommandMatches)| subcommandMatches);
+    if let Some(path)= subcommandMatches.get_one::<String>("output") {
+        // --output used to pick the format, so catch the old spelling instead of writing a file named json
+        if path == "json" || path == "text" {
+            eprintln!("Error: --output takes a file name; use --format {} to select the solution format", path);
+            process::exit(1);
+        }
+        let file= File::create(path).unwrap_or_else(|error| {
+            eprintln!("Error: Failed to create {}: {}", path, error);
+            process::exit(1);
+        });
+        OUTPUT.set(Mutex::new(file)).unwrap();
+    }
+    match matches.subcommand() {
+        Some(("generate", generateMatches)) => {
+            runGenerate(generateMatches);
+            return;
+        },
+        Some(("verify", verifyMatches)) => {
+            runVerify(verifyMatches);
+            return;
+        },
+        _ => {},
+    }
+
+    let options= getSolveOptions(&matches);
+    if matches.get_flag("batch") {
+        runBatch(&matches, &options);
+        return;
+    }
+    let files: Vec<&String>= matches.get_many::<String>("files").map_or_else(Vec::new, |files| files.collect());
+    if files.len() > 1 {
+        eprintln!("Error: Only one puzzle file is solved at a time, use --batch for several");
+        process::exit(1);
+    }
+    let path= files.first().map_or("-", |path| path.as_str());
+    let puzzle= handleInputData(path, matches.get_one::<usize>("capacity").copied()).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    let algorithm= options.algorithm;
+    let json= matches.get_one::<String>("format").unwrap() == "json";
+    let showStats= matches.get_flag("stats");
+    if let Some(&maxTubes)= matches.get_one::<usize>("extra-tubes") {
+        runExtraTubes(&puzzle, &options, maxTubes, &matches, path);
+        return;
+    }
+    if mystery::hasHiddenSlots(&puzzle.bottles) {
+        let result= solveInteractively(&puzzle, &options, json, matches.get_flag("moves"));
+        reportStats(result.as_ref().map(|solution| solution.stats), showStats);
+        match result {
+            Ok(solution) if json => outputln!("{}", json::solutionToJson(&solution, &puzzle.rowLayout)),
+            Ok(_) => {},
+            Err(SolveError::NoSolution { stats }) if json => outputln!("{}", json::noSolutionToJson(Algorithm::Astar, &stats)),
+            Err(SolveError::NoSolution { .. }) => outputln!("No solution was found"),
+            Err(SolveError::BudgetExceeded { limit, partial, stats }) =>
+                reportBudgetExceeded(Algorithm::Astar, limit, &partial, &stats, &puzzle.rowLayout, json, matches.get_flag("moves")),
+            Err(error) => exitWithError(&error, path),
+        }
+        return;
+    }
+    if matches.get_flag("hint") {
+        let result= hint(&puzzle, &options);
+        reportStats(result.as_ref().map(|hint| hint.stats), showStats);
+        match result {
+            Ok(hint) if json => outputln!("{}", json::hintToJson(&hint, algorithm)),
+            Ok(Hint { pour: Some(pour), remainingMoves, .. }) => outputln!("Hint: {}\nRemaining moves: {}", pour, remainingMoves),
+            Ok(Hint { pour: None, .. }) => outputln!("The puzzle is already solved"),
+            Err(SolveError::NoSolution { stats }) if json => outputln!("{}", json::noSolutionToJson(algorithm, &stats)),
+            Err(SolveError::NoSolution { .. }) => outputln!("No solution was found"),
+            Err(SolveError::BudgetExceeded { limit, partial, stats }) =>
+                reportBudgetExceeded(algorithm, limit, &partial, &stats, &puzzle.rowLayout, json, true),
+            Err(error) => exitWithError(&error, path),
+        }
+        return;
+    }
+    let result= if matches.get_flag("anytime") {
+        solveAnytime(&puzzle, &options, |solution| reportImprovement(solution, &puzzle.rowLayout, json))
+    } else {
+        solve(&puzzle, &options)
+    };
+    reportStats(result.as_ref().map(|solution| solution.stats), showStats);
+    match result {
+        Ok(solution) => {
+            if matches.get_flag("anytime") && !json {
+                outputln!("Best solution:");
+            }
+            if json {
+                outputln!("{}", json::solutionToJson(&solution, &puzzle.rowLayout));
+            } else if matches.get_flag("step") {
+                if let Err(error) = stepThrough(&solution, &puzzle.rowLayout) {
                     eprintln!("{}", error);
                     process::exit(1);
                 }
-                let mut bfs= BFS::new(position);
-                printSolution(bfs.solve(), &row_layout); 
+            } else if matches.get_flag("moves") {
+                printMoves(&solution);
             } else {
-                let position= PositionAstar::new(data);
-                if let Some(error) = position.isValid().err() {
-                    eprintln!("{}", error);
-                    process::exit(1);
-                }
-                let mut astar= Astar::new(position);
-                printSolution(astar.solve(), &row_layout);
+                printSolution(&solution, &puzzle.rowLayout);
+            }
+            if !json && solution.lowerBound < solution.moveCount() {
+                outputln!("This solution may not be the shortest: at least {} moves are needed", solution.lowerBound);
             }
         },
-        Err(error) => {
-            eprintln!("{}", error);
-            process::exit(1);
-        }
+        Err(SolveError::NoSolution { stats }) if json => outputln!("{}", json::noSolutionToJson(algorithm, &stats)),
+        Err(SolveError::NoSolution { .. }) => outputln!("No solution was found"),
+        Err(SolveError::BudgetExceeded { limit, partial, stats }) =>
+            reportBudgetExceeded(algorithm, limit, &partial, &stats, &puzzle.rowLayout, json, matches.get_flag("moves")),
+        Err(error) => exitWithError(&error, path),
     }
 }
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::astar::astar::*;
+use crate::astar::position_astar::*;
+use crate::bottle::*;
+use crate::puzzle::*;
+use crate::solver::*;
+use crate::stats::*;
+use crate::traits::position::*;
+
+/// Checks whether a puzzle contains hidden slots
+pub fn hasHiddenSlots(bottles: &[Bottle]) -> bool {
+    bottles.iter().any(|bottle| bottle.getContent().contains(&UNKNOWN))
+}
+
+/// Validates a puzzle with hidden slots: no visible color may exceed the bottle capacity, the hidden
+/// slots must be able to make up the missing units, and the top liquid of every bottle is visible.
+///
+/// # Returns
+/// `Ok(())` if some assignment of the hidden slots yields a valid puzzle, otherwise `Err` with a
+/// message detailing the inconsistency.
+pub fn isValidMystery(bottles: &[Bottle]) -> Result<(), String> {
+    let capacity= bottles[0].getCapacity();
+    if bottles.iter().any(|bottle| bottle.getCapacity() != capacity) {
+        return Err("Error, all bottles must have the same capacity".to_string());
+    }
+    if let Some(index)= bottles.iter().position(|bottle| bottle.isTopHidden()) {
+        return Err(format!("Error, the top liquid of bottle {} must be visible", index + 1));
+    }
+    let (counts, hidden)= countColors(bottles);
+    let mut missing= 0;
+    for (&color, &count) in counts.iter() {
+        if count > capacity {
+            return Err(format!("Error, character '{}' appears {} times, more than a bottle of {} holds", color as char, count, capacity));
+        }
+        missing += capacity - count;
+    }
+    if hidden < missing || (hidden - missing) % capacity != 0 {
+        return Err(format!("Error, {} hidden slots cannot complete the visible colors, which miss {} units", hidden, missing));
+    }
+    Ok(())
+}
+
+/// Solves a puzzle with hidden slots interactively. A* plans towards the solution until a pour
+/// uncovers a hidden slot, at which point `reveal` is asked for the color that showed up and the
+/// search resumes from the updated position. Hidden slots whose color can be deduced from the color
+/// counts are filled in without asking.
+///
+/// Every stage is planned with the knowledge available at that time, so unlike `solve` the overall
+/// sequence is not guaranteed to be the shortest possible.
+///
+/// # Arguments
+/// * `puzzle` - The puzzle, possibly containing `UNKNOWN` slots.
+/// * `options` - The rules, heuristic, limits and progress interval of every stage. Only A* can stop
+///   at an uncovered slot, and only unweighted, so other algorithms and weights are rejected.
+/// * `reveal` - Called with the positions of the stage just planned (the last one has the uncovered
+///   slot on top) and the index of the bottle to look at. Returns the revealed color.
+///
+/// # Returns
+/// `Ok(Solution)` with the full sequence of positions, or `Err(SolveError)` if the puzzle or the
+/// options are invalid, a revealed color contradicts the color counts, no solution is reachable or
+/// a stage hit a limit.
+pub fn solveMystery<F>(puzzle: &Puzzle, options: &SolveOptions, mut reveal: F) -> Result<Solution, SolveError>
+where
+    F: FnMut(&[Rc<dyn Position>], usize) -> Result<u8, String>
+{
+    if options.algorithm != Algorithm::Astar || options.weight > 1.0 {
+        return Err(SolveError::InvalidPuzzle("Error, puzzles with hidden slots can only be solved with unweighted A*".to_string()));
+    }
+    isValidMystery(&puzzle.bottles).map_err(SolveError::InvalidPuzzle)?;
+    let mut bottles= puzzle.bottles.clone();
+    let mut positions: Vec<Rc<dyn Position>>= Vec::new();
+    let mut stats= SearchStats::default();
+    loop {
+        deduceHidden(&mut bottles);
+        let mut astar= Astar::new(PositionAstar::new(bottles));
+        astar.setLimits(options.limits);
+        astar.setHeuristic(options.heuristic);
+        astar.setRules(options.rules);
+        astar.setProgress(options.progress);
+        let stage= astar.solveUntil(|position| position.isSolved() || position.getBottles().iter().any(Bottle::isTopHidden));
+        stats.add(&astar.getStats());
+        let mut stage= outcomeToResult(stage, stats)?;
+        // The first position of a stage repeats the last position of the previous one
+        positions.pop();
+        let last= stage.last().unwrap().getBottles().clone();
+        let Some(index)= last.iter().position(Bottle::isTopHidden) else {
+            positions.append(&mut stage);
+            // Each stage was planned without knowing the hidden colors, so nothing is proven
+            return Ok(Solution { positions, algorithm: Algorithm::Astar, stats, lowerBound: 0 });
+        };
+        let color= reveal(&stage, index).map_err(SolveError::InvalidPuzzle)?;
+        bottles= last;
+        let top= bottles[index].getTopIndex();
+        bottles[index].content[top]= color;
+        isValidMystery(&bottles)
+            .map_err(|error| SolveError::InvalidPuzzle(format!("Revealed color '{}' is impossible. {}", color as char, error)))?;
+        positions.append(&mut stage);
+        // Keep the revealed color in the junction position so pours can be derived across stages
+        positions.pop();
+        positions.push(Rc::new(PositionAstar::new(bottles.clone())));
+    }
+}
+
+/// Counts the visible units of every color and the number of hidden slots
+fn countColors(bottles: &[Bottle]) -> (HashMap<u8, usize>, usize) {
+    let mut counts= HashMap::new();
+    let mut hidden= 0;
+    for bottle in bottles {
+        for &slot in bottle.getContent() {
+            match slot {
+                EMPTY => {},
+                UNKNOWN => hidden += 1,
+                color => *counts.entry(color).or_insert(0) += 1,
+            }
+        }
+    }
+    (counts, hidden)
+}
+
+/// Fills in all hidden slots if they can only be one color: exactly one visible color misses units
+/// and the hidden slots are exactly that many
+fn deduceHidden(bottles: &mut [Bottle]) {
+    let capacity= bottles[0].getCapacity();
+    let (counts, hidden)= countColors(bottles);
+    let incomplete: Vec<(&u8, &usize)>= counts.iter().filter(|(_, &count)| count < capacity).collect();
+    if hidden == 0 || incomplete.len() != 1 || capacity - incomplete[0].1 != hidden {
+        return;
+    }
+    let color= *incomplete[0].0;
+    for bottle in bottles.iter_mut() {
+        for slot in bottle.content.iter_mut() {
+            if *slot == UNKNOWN {
+                *slot= color;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::budget::*;
+
+    #[test]
+    fn validateMystery() {
+        assert!(isValidMystery(&Puzzle::parse("A?B;?AB;C?C;;", None).unwrap().bottles).is_ok());
+        // Two hidden slots but no missing units
+        assert!(isValidMystery(&Puzzle::parse("A?B;BAB;?A;", None).unwrap().bottles).is_err());
+        // Hidden top
+        assert!(isValidMystery(&Puzzle::parse("AB?;BAB;A;", None).unwrap().bottles).is_err());
+        // A whole hidden color
+        assert!(isValidMystery(&Puzzle::parse("AAA;??B;?BB;;", None).unwrap().bottles).is_ok());
+    }
+
+    #[test]
+    fn solveWithReveals() {
+        // The hidden slots are A and B
+        let puzzle= Puzzle::parse("?BA;?AB;;", None).unwrap();
+        let mut asked= Vec::new();
+        let solution= solveMystery(&puzzle, &SolveOptions::default(), |stage, index| {
+            assert!(stage.last().unwrap().getBottles()[index].isTopHidden());
+            asked.push(index);
+            Ok(if index == 0 { b'A' } else { b'B' })
+        }).unwrap();
+        assert!(!asked.is_empty());
+        let last= solution.positions.last().unwrap().getBottles();
+        assert!(last.iter().all(Bottle::isSolved));
+        assert_eq!(solution.getPours().len(), solution.moveCount());
+    }
+
+    #[test]
+    fn rejectImpossibleReveal() {
+        let puzzle= Puzzle::parse("?BA;?AB;;", None).unwrap();
+        let result= solveMystery(&puzzle, &SolveOptions::default(), |_, _| Ok(b'C'));
+        assert!(matches!(result, Err(SolveError::InvalidPuzzle(_))));
+    }
+
+    #[test]
+    fn mysteryOptions() {
+        let puzzle= Puzzle::parse("?BA;?AB;;", None).unwrap();
+        let bfs= SolveOptions { algorithm: Algorithm::BFS, ..SolveOptions::default() };
+        assert!(matches!(solveMystery(&puzzle, &bfs, |_, _| Ok(b'A')), Err(SolveError::InvalidPuzzle(_))));
+        let limits= SearchLimits { maxExpanded: Some(1), ..SearchLimits::default() };
+        let limited= SolveOptions { limits, ..SolveOptions::default() };
+        assert!(matches!(solveMystery(&puzzle, &limited, |_, _| Ok(b'A')), Err(SolveError::BudgetExceeded { .. })));
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::rc::Rc;
+use crate::bottle::*;
+use crate::traits::position::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A single move of the game: liquid poured from one bottle into another
+pub struct Pour {
+    pub from: usize, // Index of the source bottle in input order
+    pub to: usize, // Index of the destination bottle in input order
+    pub color: u8, // The color being poured
+    pub amount: usize, // How many units were transferred
+}
+
+impl Pour {
+    /// Derives the pour that turns one arrangement of bottles into the next.
+    ///
+    /// # Arguments
+    /// * `previous` - The bottles before the pour.
+    /// * `next` - The bottles after the pour, in the same order.
+    ///
+    /// # Returns
+    /// `Some(Pour)` if exactly one bottle lost liquid and exactly one bottle received it, `None` otherwise.
+    pub fn between(previous: &[Bottle], next: &[Bottle]) -> Option<Pour> {
+        if previous.len() != next.len() {
+            return None;
+        }
+        let mut from= None;
+        let mut to= None;
+        for (index, (before, after)) in previous.iter().zip(next).enumerate() {
+            let (filledBefore, filledAfter)= (Pour::filledSlots(before), Pour::filledSlots(after));
+            if filledBefore > filledAfter && from.replace((index, filledBefore - filledAfter)).is_some() {
+                return None;
+            }
+            if filledAfter > filledBefore && to.replace((index, filledAfter - filledBefore)).is_some() {
+                return None;
+            }
+        }
+        let ((from, lost), (to, gained))= (from?, to?);
+        if lost != gained {
+            return None;
+        }
+        let destination= &next[to];
+        Some(Pour { from, to, color: destination.content[destination.getTopIndex()], amount: gained })
+    }
+
+    /// Derives the pours between every two consecutive positions of a solution.
+    ///
+    /// # Panics
+    /// If two consecutive positions are not one pour apart.
+    pub fn fromPositions(positions: &[Rc<dyn Position>]) -> Vec<Pour> {
+        positions
+            .windows(2)
+            .map(|pair| Pour::between(pair[0].getBottles(), pair[1].getBottles())
+                 .expect("Consecutive positions must be one pour apart"))
+            .collect()
+    }
+
+    #[inline]
+    fn filledSlots(bottle: &Bottle) -> usize {
+        bottle.getContent().iter().filter(|&&slot| slot != EMPTY).count()
+    }
+}
+
+impl fmt::Display for Pour {
+    /// Formats the pour with 1-based bottle numbers, e.g. "pour 3 → 17 (2 units of D)"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pour {} → {} ({} unit{} of {})",
+               self.from + 1,
+               self.to + 1,
+               self.amount,
+               if self.amount == 1 { "" } else { "s" },
+               self.color as char)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn pourBetween() {
+        let mut bottles= vec![
+            Bottle::newChars(&[ 'A', 'A', ' ', ' ']),
+            Bottle::newChars(&[ 'B', 'B', 'A', 'A']),
+            Bottle::newChars(&[ ' ', ' ', ' ', ' ']),
+        ];
+        let previous= bottles.clone();
+        let (left, right)= bottles.split_at_mut(1);
+        assert!(left[0].fillFrom(&mut right[0]));
+        let pour= Pour::between(&previous, &bottles).unwrap();
+        assert_eq!(pour, Pour { from: 1, to: 0, color: b'A', amount: 2 });
+        assert_eq!(pour.to_string(), "pour 2 → 1 (2 units of A)");
+        assert_eq!(Pour::between(&previous, &previous), None);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::bottle::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A liquid sort puzzle as read from its text representation
+pub struct Puzzle {
+    pub bottles: Vec<Bottle>, // Bottles in input order
+    pub rowLayout: Vec<usize>, // How many bottles each input row holds, used to shape the output
+}
+
+impl Puzzle {
+    pub fn new(bottles: Vec<Bottle>, rowLayout: Vec<usize>) -> Self {
+        Self { bottles, rowLayout }
+    }
+
+    /// Parses the puzzle text format.
+    ///
+    /// # Operation
+    /// - Each line represents a row of bottle configurations separated by ';'.
+    /// - Each bottle can contain up to `capacity` ASCII characters representing its content from the
+    ///   bottom up; shorter entries are padded with spaces.
+    /// - If `capacity` is `None`, it is inferred as the length of the longest bottle in the input.
+    /// - A bottle of another size has its number of slots after a '/', e.g. `AB/6`.
+    /// - A locked bottle ends with '@' and its condition: a number of moves after which it opens, or
+    ///   the color another bottle must be completed with, e.g. `AB@3`, `AB/6@R`.
+    /// - Lines starting with '#' are considered as comments and are ignored.
+    /// - Lines that are entirely whitespace are also ignored.
+    ///
+    /// # Arguments
+    /// * `input` - The puzzle text.
+    /// * `capacity` - The number of slots in every bottle without a size of its own, if known upfront.
+    ///
+    /// # Returns
+    /// - `Ok(Puzzle)` if the input is successfully read and validated.
+    /// - `Err(String)` if there are any input errors like non-ASCII characters or incorrect bottle lengths,
+    ///   naming the line and the bottle at fault.
+    pub fn parse(input: &str, capacity: Option<usize>) -> Result<Puzzle, String> {
+        let mut rawBottles: Vec<RawBottle>= Vec::new();
+        let mut rowLayout: Vec<usize>= Vec::new();
+        for (lineIndex, raw_line) in input.lines().enumerate() {
+            let line = raw_line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut row_count = 0;
+            for text in line.split(';') {
+                row_count += 1;
+                if !text.is_ascii() {
+                    return Err(format!("Error: Line {}, bottle {} \"{}\": All characters must be ASCII", lineIndex + 1, row_count, text));
+                }
+                let (bottle, lock)= match text.split_once('@') {
+                    Some((content, lock)) => match parseLock(lock.trim()) {
+                        Some(lock) => (content, lock),
+                        None => return Err(format!("Error: Line {}, bottle {} \"{}\": The lock after '@' must be a number of moves between 1 and 255 or a color.",
+                                                   lineIndex + 1, row_count, text)),
+                    },
+                    None => (text, Lock::Open),
+                };
+                let (content, size)= match bottle.split_once('/') {
+                    Some((content, size)) => match size.trim().parse::<usize>() {
+                        Ok(size) if (1..=MAX_CAPACITY).contains(&size) => (content, Some(size)),
+                        _ => return Err(format!("Error: Line {}, bottle {} \"{}\": The size after '/' must be a number between 1 and {}.",
+                                                lineIndex + 1, row_count, bottle, MAX_CAPACITY)),
+                    },
+                    None => (bottle, None),
+                };
+                rawBottles.push(RawBottle { line: lineIndex + 1, column: row_count, text, content, size, lock });
+            }
+            rowLayout.push(row_count);
+        }
+        if rawBottles.is_empty() {
+            return Err("Error: No bottle data found.".to_string());
+        }
+        // Only bottles without a size of their own tell the default one
+        let capacity= match capacity {
+            Some(capacity) => capacity,
+            None => {
+                let unsizedBottles= rawBottles.iter().filter(|raw| raw.size.is_none());
+                match unsizedBottles.map(|raw| raw.content.len()).max() {
+                    // Nothing tells how big the empty bottles are
+                    Some(0) => {
+                        let empty= rawBottles.iter().find(|raw| raw.size.is_none()).unwrap();
+                        return Err(format!("Error: Line {}, bottle {} \"{}\": An empty bottle needs a size, e.g. /3.", empty.line, empty.column, empty.text));
+                    },
+                    longest => longest.unwrap_or(1),
+                }
+            },
+        };
+        if capacity == 0 || capacity > MAX_CAPACITY {
+            return Err(format!("Error: Bottle capacity must be between 1 and {}.", MAX_CAPACITY));
+        }
+        let mut bottles: Vec<Bottle>= Vec::new();
+        for &RawBottle { line, column, content: bottle, size, lock, .. } in &rawBottles {
+            let capacity= size.unwrap_or(capacity);
+            if bottle.len() > capacity {
+                return Err(format!("Error: Line {}, bottle {} \"{}\": Each bottle must contain at most {} characters.", line, column, bottle, capacity));
+            }
+            let paddedBottle= format!("{:width$}", bottle, width = capacity);
+            bottles.push(Bottle::new(paddedBottle.as_bytes()).withLock(lock));
+        }
+        for &RawBottle { line, column, text, lock, .. } in &rawBottles {
+            if let Lock::Color(color)= lock {
+                if !bottles.iter().any(|other| other.getContent().contains(&color)) {
+                    return Err(format!("Error: Line {}, bottle {} \"{}\": No bottle holds the color '{}' the lock waits for.",
+                                       line, column, text, color as char));
+                }
+            }
+        }
+        // Locks waiting for a bottle that is completed from the start are open
+        openLocks(&mut bottles);
+        Ok(Puzzle::new(bottles, rowLayout))
+    }
+
+    /// Writes the puzzle in the text format `parse` reads: one line per row, bottles separated by
+    /// ';' as `bottlesToText` writes them
+    pub fn toText(&self) -> String {
+        let texts= bottlesToText(&self.bottles);
+        let mut out= String::new();
+        let mut rowStart= 0;
+        for rowLength in &self.rowLayout {
+            out.push_str(&texts[rowStart..rowStart + rowLength].join(";"));
+            out.push('\n');
+            rowStart += rowLength;
+        }
+        out
+    }
+
+    /// Returns the puzzle with `count` more empty bottles of the first bottle's size in a row of their
+    /// own, as added by the "extra tube" power-up of many games
+    pub fn withExtraTubes(&self, count: usize) -> Puzzle {
+        let mut puzzle= self.clone();
+        if count > 0 {
+            let tube= Bottle::new(&vec![EMPTY; self.getCapacity()]);
+            puzzle.bottles.extend(std::iter::repeat_n(tube, count));
+            puzzle.rowLayout.push(count);
+        }
+        puzzle
+    }
+
+    #[inline]
+    /// Returns the number of slots in the first bottle, which all bottles have unless the puzzle
+    /// mixes sizes
+    pub fn getCapacity(&self) -> usize {
+        self.bottles[0].getCapacity()
+    }
+}
+
+/// Writes every bottle from the bottom up without its empty slots, as `Puzzle::parse` reads it, and
+/// locked bottles followed by their lock. If the bottles differ in size, every one is followed by
+/// its size: leaving it out for some size would have `parse` guess that size from the longest of
+/// those bottles, which may not be full
+pub fn bottlesToText(bottles: &[Bottle]) -> Vec<String> {
+    let mixedSizes= bottles.iter().any(|bottle| bottle.getCapacity() != bottles[0].getCapacity());
+    bottles.iter()
+        .map(|bottle| {
+            let content= String::from_utf8_lossy(bottle.getContent()).trim_end_matches(EMPTY as char).to_string();
+            let content= if mixedSizes { format!("{}/{}", content, bottle.getCapacity()) } else { content };
+            format!("{}{}", content, bottle.lock)
+        })
+        .collect()
+}
+
+/// A bottle as read from the input, before the size of the bottles without one of their own is known
+struct RawBottle<'a> {
+    line: usize, // 1-based
+    column: usize, // 1-based position in the line
+    text: &'a str, // The whole bottle as written, quoted in errors
+    content: &'a str, // The colors without size and lock
+    size: Option<usize>, // The size after '/', if any
+    lock: Lock,
+}
+
+/// Reads the condition of a lock: a number of moves, or a single color
+fn parseLock(text: &str) -> Option<Lock> {
+    match text.as_bytes() {
+        [color] if !color.is_ascii_digit() && color.is_ascii_graphic() && ![UNKNOWN, b';', b'/', b'@'].contains(color) => Some(Lock::Color(*color)),
+        _ => match text.parse::<u8>() {
+            Ok(moves) if moves > 0 => Some(Lock::Moves(moves)),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parseRows() {
+        let puzzle= Puzzle::parse("# comment\nAB;BA\n\n;\n", None).unwrap();
+        assert_eq!(puzzle.rowLayout, vec![2, 2]);
+        assert_eq!(puzzle.getCapacity(), 2);
+        assert_eq!(puzzle.bottles[1], Bottle::newChars(&[ 'B', 'A' ]));
+        assert_eq!(puzzle.bottles[3], Bottle::newChars(&[ ' ', ' ' ]));
+        assert_eq!(puzzle.toText(), "AB;BA\n;\n");
+    }
+
+    #[test]
+    fn parseExplicitCapacity() {
+        let puzzle= Puzzle::parse("AB;BA;;", Some(3)).unwrap();
+        assert_eq!(puzzle.bottles[0], Bottle::newChars(&[ 'A', 'B', ' ' ]));
+        assert_eq!(Puzzle::parse("# too long\nAB;BA\n;ABC;", Some(2)),
+                   Err("Error: Line 3, bottle 2 \"ABC\": Each bottle must contain at most 2 characters.".to_string()));
+        assert_eq!(Puzzle::parse("AB;BÄ;;", None), Err("Error: Line 1, bottle 2 \"BÄ\": All characters must be ASCII".to_string()));
+        assert!(Puzzle::parse("# nothing here\n", None).is_err());
+    }
+
+    #[test]
+    fn parseMixedSizes() {
+        let puzzle= Puzzle::parse("ABA;BAB;AAB/6\n;/6;BB/2", None).unwrap();
+        assert_eq!(puzzle.getCapacity(), 3);
+        assert_eq!(puzzle.bottles[2], Bottle::new(b"AAB   "));
+        assert_eq!(puzzle.bottles[4], Bottle::new(b"      "));
+        assert_eq!(puzzle.bottles[5], Bottle::new(b"BB"));
+        assert_eq!(Puzzle::parse(&puzzle.toText(), None), Ok(puzzle));
+        // The first bottle is bigger than the others and not full
+        let puzzle= Puzzle::parse("AB/6;ABC;BCA;CAB;;", None).unwrap();
+        assert_eq!(puzzle.toText(), "AB/6;ABC/3;BCA/3;CAB/3;/3;/3\n");
+        assert_eq!(Puzzle::parse(&puzzle.toText(), None), Ok(puzzle));
+        assert_eq!(Puzzle::parse("AB;BA\nABC/2", None),
+                   Err("Error: Line 2, bottle 1 \"ABC\": Each bottle must contain at most 2 characters.".to_string()));
+        assert_eq!(Puzzle::parse("AB;BA/x", None),
+                   Err("Error: Line 1, bottle 2 \"BA/x\": The size after '/' must be a number between 1 and 8.".to_string()));
+        assert!(Puzzle::parse("AB/9", None).is_err());
+        assert_eq!(Puzzle::parse("ABA/3;BAB/3;;", None),
+                   Err("Error: Line 1, bottle 3 \"\": An empty bottle needs a size, e.g. /3.".to_string()));
+        assert_eq!(Puzzle::parse("ABA/3;BAB/3;;", Some(3)).unwrap().toText(), "ABA;BAB;;\n");
+    }
+
+    #[test]
+    fn parseLocks() {
+        let puzzle= Puzzle::parse("AB@3;BA/3@B\nAB;BA;AB@B;@A", None).unwrap();
+        assert_eq!(puzzle.bottles[0].lock, Lock::Moves(3));
+        assert_eq!(puzzle.bottles[1], Bottle::new(b"BA ").withLock(Lock::Color(b'B')));
+        assert_eq!(puzzle.bottles[5], Bottle::new(b"  ").withLock(Lock::Color(b'A')));
+        assert!(!puzzle.bottles[2].isLocked());
+        assert_eq!(Puzzle::parse(&puzzle.toText(), None), Ok(puzzle));
+        // A lock waiting for a color completed from the start is open
+        assert!(!Puzzle::parse("AA;BB@A", None).unwrap().bottles[1].isLocked());
+        assert_eq!(Puzzle::parse("AB;BA@0", None),
+                   Err("Error: Line 1, bottle 2 \"BA@0\": The lock after '@' must be a number of moves between 1 and 255 or a color.".to_string()));
+        assert!(Puzzle::parse("AB;BA@RR", None).is_err());
+        assert_eq!(Puzzle::parse("AB;BA@R", None),
+                   Err("Error: Line 1, bottle 2 \"BA@R\": No bottle holds the color 'R' the lock waits for.".to_string()));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::bottle::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How much liquid a single pour moves. Games differ here, and with them the length of a solution.
+pub enum PourRule {
+    #[default]
+    Run, // The whole run of the top color, or as much of it as fits
+    SingleUnit, // One slot per pour
+    WholeRun, // The whole run of the top color, and only if all of it fits
+}
+
+impl PourRule {
+    pub const ALL: [PourRule; 3]= [PourRule::Run, PourRule::SingleUnit, PourRule::WholeRun];
+
+    /// Returns the name of the rule as used on the command line
+    pub fn getName(&self) -> &'static str {
+        match self {
+            PourRule::Run => "run",
+            PourRule::SingleUnit => "unit",
+            PourRule::WholeRun => "whole",
+        }
+    }
+
+    /// Returns the rule with the given command line name, if any
+    pub fn fromName(name: &str) -> Option<PourRule> {
+        PourRule::ALL.into_iter().find(|rule| rule.getName() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the positions reachable in one move are generated
+pub struct Rules {
+    // Skip pours leading to the same position as no pour at all or as another pour, up to the
+    // order of the bottles. Such pours are never part of a shortest solution
+    pub pruning: bool,
+    pub pour: PourRule,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self { pruning: true, pour: PourRule::default() }
+    }
+}
+
+impl Rules {
+    /// Pours from `source` into `destination` as the pour rule allows. Locked bottles take no part.
+    ///
+    /// # Returns
+    /// `true` if anything was poured, `false` (leaving both bottles untouched) otherwise.
+    pub fn pour(&self, destination: &mut Bottle, source: &mut Bottle) -> bool {
+        if destination.isLocked() || source.isLocked() {
+            return false;
+        }
+        match self.pour {
+            PourRule::Run => destination.fillFrom(source),
+            PourRule::SingleUnit => destination.fillOneFrom(source),
+            PourRule::WholeRun => destination.fillWholeFrom(source),
+        }
+    }
+
+    /// Undoes a pour of `amount` slots from `source` into `destination`, see `Bottle::unfillInto`.
+    ///
+    /// # Returns
+    /// `true` if the bottles now hold a position that pouring from `source` into `destination`
+    /// leads back from, `false` (leaving both bottles untouched) otherwise.
+    pub fn unpour(&self, destination: &mut Bottle, source: &mut Bottle, amount: usize) -> bool {
+        match self.pour {
+            PourRule::Run => destination.unfillInto(source, amount),
+            PourRule::SingleUnit => amount == 1 && destination.unfillOneInto(source),
+            PourRule::WholeRun => {
+                // Nothing of the poured color may have been left behind
+                let color= destination.content[destination.getTopIndex()];
+                (source.isEmpty() || source.content[source.getTopIndex()] != color) && destination.unfillInto(source, amount)
+            },
+        }
+    }
+
+    /// Checks whether the pour from bottle `from` into bottle `to` can be skipped. Only pours whose
+    /// result is a permutation of the bottles before, or of the result of another pour that is
+    /// kept, are skipped, so the positions reachable in one move stay the same up to permutation:
+    /// * A bottle holding a single color poured into an empty bottle of the same size.
+    /// * Pours into an empty bottle when an open empty bottle of the same size comes before it.
+    /// * Pours between two bottles of the same size holding only the same color: only the one from
+    ///   the bottle with less, or else the earlier one, into the other is kept.
+    ///
+    /// With single unit pours a bottle only moves over as a whole if it holds one slot, and two
+    /// bottles of the same color end up the same either way only if they hold as much. Pours of
+    /// whole runs are the pours of runs that move everything, so the same pours are skipped.
+    ///
+    /// While a lock is counting moves down, the position after a pour that permutes the bottles
+    /// differs from the one before in the count, so such pours are waiting moves and only pours into
+    /// a later empty bottle are skipped.
+    ///
+    /// # Arguments
+    /// * `bottles` - The position before the pour.
+    /// * `from` - Index of the bottle poured from.
+    /// * `to` - Index of the bottle poured into.
+    pub fn isRedundant(&self, bottles: &[Bottle], from: usize, to: usize) -> bool {
+        if !self.pruning {
+            return false;
+        }
+        let (source, destination)= (&bottles[from], &bottles[to]);
+        let sameCapacity= source.getCapacity() == destination.getCapacity();
+        let movesAll= match self.pour {
+            PourRule::Run | PourRule::WholeRun => true,
+            PourRule::SingleUnit => source.getLevel() == 1,
+        };
+        let waiting= bottles.iter().any(|bottle| matches!(bottle.lock, Lock::Moves(_)));
+        if destination.isEmpty() {
+            let capacity= destination.getCapacity();
+            return bottles[..to].iter().any(|bottle| bottle.isEmpty() && !bottle.isLocked() && bottle.getCapacity() == capacity)
+                || (!waiting && sameCapacity && source.isMonochrome() && movesAll);
+        }
+        let sameColor= !waiting
+            && sameCapacity
+            && source.isMonochrome()
+            && destination.isMonochrome()
+            && source.content[0] == destination.content[0];
+        match self.pour {
+            // The reverse pour must be possible, i.e. the source must have room left
+            PourRule::Run | PourRule::WholeRun => sameColor
+                && source.getLevel() < source.getCapacity()
+                && (source.getLevel(), from) > (destination.getLevel(), to),
+            PourRule::SingleUnit => sameColor && source.getLevel() == destination.getLevel() && from > to,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn bottles(contents: &[&str]) -> Vec<Bottle> {
+        contents.iter().map(|content| Bottle::new(content.as_bytes())).collect()
+    }
+
+    #[test]
+    fn redundantPours() {
+        let rules= Rules::default();
+        let position= bottles(&["AAAA", "BB  ", "CAB ", "    ", "    ", "BC  ", "  "]);
+        // A solved or a single colored bottle into an empty one
+        assert!(rules.isRedundant(&position, 0, 3));
+        assert!(rules.isRedundant(&position, 1, 3));
+        // Only the first empty bottle of a size is poured into
+        assert!(!rules.isRedundant(&position, 2, 3));
+        assert!(rules.isRedundant(&position, 2, 4));
+        assert!(!rules.isRedundant(&position, 2, 6));
+        // A single colored bottle into a smaller empty one does change the position
+        assert!(!rules.isRedundant(&position, 1, 6));
+        // Mixed bottles are poured as usual
+        assert!(!rules.isRedundant(&position, 2, 5));
+        assert!(!rules.isRedundant(&bottles(&["BB  ", "AB  "]), 0, 1));
+        assert!(!Rules { pruning: false, ..Rules::default() }.isRedundant(&position, 0, 3));
+    }
+
+    #[test]
+    fn singleColorPairs() {
+        let rules= Rules::default();
+        // Either direction leaves one bottle with 3 and one with 1, so only one is kept
+        let position= bottles(&["AA  ", "AA  "]);
+        assert!(!rules.isRedundant(&position, 0, 1));
+        assert!(rules.isRedundant(&position, 1, 0));
+        let position= bottles(&["AAA ", "A   "]);
+        assert!(rules.isRedundant(&position, 0, 1));
+        assert!(!rules.isRedundant(&position, 1, 0));
+    }
+
+    #[test]
+    fn singleUnitPours() {
+        let rules= Rules { pour: PourRule::SingleUnit, ..Rules::default() };
+        let (mut destination, mut source)= (Bottle::new(b"B   "), Bottle::new(b"ABB "));
+        assert!(rules.pour(&mut destination, &mut source));
+        assert_eq!((&destination, &source), (&Bottle::new(b"BB  "), &Bottle::new(b"AB  ")));
+        assert!(rules.unpour(&mut destination, &mut source, 1));
+        assert_eq!((&destination, &source), (&Bottle::new(b"B   "), &Bottle::new(b"ABB ")));
+        assert!(!rules.unpour(&mut destination, &mut source, 2));
+        // Only one slot of a single colored bottle moves into an empty one, which does change the position
+        let position= bottles(&["AA  ", "    ", "A   "]);
+        assert!(!rules.isRedundant(&position, 0, 1));
+        assert!(rules.isRedundant(&position, 2, 1));
+        // Both directions between bottles with as much of a color swap them, unequal ones do not
+        assert!(!rules.isRedundant(&bottles(&["AA  ", "AA  "]), 0, 1));
+        assert!(rules.isRedundant(&bottles(&["AA  ", "AA  "]), 1, 0));
+        assert!(!rules.isRedundant(&bottles(&["AAA ", "A   "]), 0, 1));
+        assert!(!rules.isRedundant(&bottles(&["AAA ", "A   "]), 1, 0));
+        assert_eq!(PourRule::fromName("unit"), Some(PourRule::SingleUnit));
+        assert_eq!(PourRule::fromName("drop"), None);
+    }
+
+    #[test]
+    fn wholeRunPours() {
+        let rules= Rules { pour: PourRule::WholeRun, ..Rules::default() };
+        // Two of the three A's would fit
+        let (mut destination, mut source)= (Bottle::new(b"BA  "), Bottle::new(b"BAAA"));
+        assert!(!rules.pour(&mut destination, &mut source));
+        let (mut destination, mut source)= (Bottle::new(b"A   "), Bottle::new(b"BAA "));
+        assert!(rules.pour(&mut destination, &mut source));
+        assert_eq!((&destination, &source), (&Bottle::new(b"AAA "), &Bottle::new(b"B   ")));
+        assert!(rules.unpour(&mut destination, &mut source, 2));
+        assert_eq!((&destination, &source), (&Bottle::new(b"A   "), &Bottle::new(b"BAA ")));
+        // A pour that stopped at a full bottle, leaving some of the color behind, is not a whole one
+        let (mut destination, mut source)= (Bottle::new(b"AAAA"), Bottle::new(b"BA  "));
+        assert!(Rules::default().unpour(&mut destination.clone(), &mut source.clone(), 1));
+        assert!(!rules.unpour(&mut destination, &mut source, 1));
+    }
+
+    #[test]
+    fn lockedBottles() {
+        let rules= Rules::default();
+        let (mut destination, mut source)= (Bottle::new(b"A   ").withLock(Lock::Moves(1)), Bottle::new(b"BA  "));
+        assert!(!rules.pour(&mut destination, &mut source));
+        assert!(!rules.pour(&mut source, &mut destination));
+        assert_eq!(source, Bottle::new(b"BA  "));
+        // A locked empty bottle is no substitute for an open one
+        let mut position= bottles(&["    ", "    ", "BA  "]);
+        position[0].lock= Lock::Color(b'A');
+        assert!(!rules.isRedundant(&position, 2, 1));
+        // While a lock counts down, swapping bottles is a move spent waiting
+        let mut position= bottles(&["AA  ", "    ", "    ", "A   ", "BA  "]);
+        position[4].lock= Lock::Moves(2);
+        assert!(!rules.isRedundant(&position, 0, 1));
+        assert!(!rules.isRedundant(&position, 0, 3));
+        assert!(rules.isRedundant(&position, 0, 2));
+    }
+}
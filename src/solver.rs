@@ -0,0 +1,569 @@
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+use crate::astar::anytime::*;
+use crate::astar::astar::*;
+use crate::astar::position_astar::*;
+use crate::bfs::bfs::*;
+use crate::bfs::bidirectional::*;
+use crate::idastar::idastar::*;
+use crate::bfs::position_bfs::*;
+use crate::bottle::*;
+use crate::budget::*;
+use crate::pour::*;
+use crate::mystery::*;
+use crate::puzzle::*;
+use crate::rules::*;
+use crate::stats::*;
+use crate::traits::position::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// The search algorithm used to solve a puzzle. All of them guarantee the fewest possible moves
+pub enum Algorithm {
+    #[default]
+    Astar,
+    BFS,
+    IDAstar,
+    Bidirectional,
+}
+
+impl Algorithm {
+    /// Returns the name of the algorithm as used on the command line
+    pub fn getName(&self) -> &'static str {
+        match self {
+            Algorithm::Astar => "astar",
+            Algorithm::BFS => "bfs",
+            Algorithm::IDAstar => "idastar",
+            Algorithm::Bidirectional => "bidirectional",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Knobs controlling how `solve` searches for a solution
+pub struct SolveOptions {
+    pub algorithm: Algorithm,
+    pub limits: SearchLimits, // Resource caps, unlimited by default
+    // Number of threads expanding the positions of every BFS move. A* always runs on one thread
+    pub threads: usize,
+    // Maximum number of positions in the IDA* transposition table, 0 to run without one
+    pub transpositionTable: usize,
+    // Factor applied to the A* heuristic. Above 1 solutions are found faster, but may be up to this
+    // many times longer than the shortest one
+    pub weight: f64,
+    // Estimate of the moves left guiding A* and IDA*. BFS needs none
+    pub heuristic: Heuristic,
+    // Which pours are made. Pruning, on by default, skips pours that never shorten a solution
+    pub rules: Rules,
+    // Interval between progress reports of the search on stderr, `None` to keep quiet
+    pub progress: Option<Duration>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        Self { algorithm: Algorithm::default(), limits: SearchLimits::default(), threads: 1, transpositionTable: 0, weight: 1.0,
+               heuristic: Heuristic::default(), rules: Rules::default(), progress: None }
+    }
+}
+
+/// A sequence of positions leading from the puzzle to a solved state, the shortest possible unless
+/// a weighted or anytime search was asked for
+pub struct Solution {
+    pub positions: Vec<Rc<dyn Position>>, // The initial position first, the solved position last
+    pub algorithm: Algorithm,
+    pub stats: SearchStats,
+    // Proven minimum number of moves of any solution. Equals `moveCount()` if this one is the shortest
+    pub lowerBound: usize,
+}
+
+impl Solution {
+    #[inline]
+    /// Returns the number of pours in the solution
+    pub fn moveCount(&self) -> usize {
+        self.positions.len() - 1
+    }
+
+    /// Returns the pours leading from each position of the solution to the next one
+    pub fn getPours(&self) -> Vec<Pour> {
+        Pour::fromPositions(&self.positions)
+    }
+}
+
+/// A solution spending the fewest extra empty bottles possible, see `solveWithExtraTubes`
+pub struct ExtraTubes {
+    pub puzzle: Puzzle, // The puzzle with the extra tubes as its last row
+    pub solution: Solution, // Solves `puzzle`, the shortest possible with this many extra tubes
+    // For every extra tube in bottle order, the 1-based step of the first pour into it. The tube is
+    // needed from that step on, so it is bought just before it
+    pub addBefore: Vec<usize>,
+}
+
+impl ExtraTubes {
+    #[inline]
+    /// Returns the number of extra tubes the solution needs
+    pub fn count(&self) -> usize {
+        self.addBefore.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The next move of an optimal continuation from some position
+pub struct Hint {
+    pub pour: Option<Pour>, // `None` if the position is already solved
+    pub remainingMoves: usize, // Length of the optimal continuation, including `pour`
+    pub stats: SearchStats,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Reasons `solve` can fail
+pub enum SolveError {
+    InvalidPuzzle(String), // The puzzle breaks the game rules, e.g. a color does not fill a bottle
+    NoSolution { stats: SearchStats }, // The search space was exhausted without reaching a solved position
+    // A search limit was hit before the search finished. `partial` holds the bottles of every
+    // position on the path to the most promising position found, the initial position first
+    BudgetExceeded { limit: Limit, partial: Vec<Vec<Bottle>>, stats: SearchStats },
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::InvalidPuzzle(error) => write!(f, "{}", error),
+            SolveError::NoSolution { .. } => write!(f, "No solution was found"),
+            SolveError::BudgetExceeded { limit, partial, .. } =>
+                write!(f, "The {} limit was exceeded before a solution was found. Best partial progress: {} moves", limit, partial.len() - 1),
+        }
+    }
+}
+
+impl SolveError {
+    /// Returns the counters of the search that failed, if it ran at all
+    pub fn getStats(&self) -> Option<SearchStats> {
+        match self {
+            SolveError::InvalidPuzzle(_) => None,
+            SolveError::NoSolution { stats } | SolveError::BudgetExceeded { stats, .. } => Some(*stats),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+/// Solves a puzzle with the fewest possible moves.
+///
+/// # Arguments
+/// * `puzzle` - The puzzle to solve.
+/// * `options` - The algorithm and other search settings.
+///
+/// # Returns
+/// `Ok(Solution)` with the shortest path to a solved position, `Err(SolveError)` if the puzzle is
+/// invalid or cannot be solved.
+pub fn solve(puzzle: &Puzzle, options: &SolveOptions) -> Result<Solution, SolveError> {
+    if hasHiddenSlots(&puzzle.bottles) {
+        return Err(SolveError::InvalidPuzzle("Error, the puzzle has hidden slots. Use solveMystery to solve it interactively".to_string()));
+    }
+    if options.algorithm == Algorithm::Astar && options.weight > 1.0 {
+        let mut weighted= newAnytimeAstar(puzzle, options)?;
+        let positions= weighted.solve();
+        let stats= weighted.getStats();
+        return outcomeToResult(positions, stats)
+            .map(|positions| Solution { positions, algorithm: options.algorithm, stats, lowerBound: weighted.getLowerBound() as usize });
+    }
+    let (positions, stats)= match options.algorithm {
+        Algorithm::BFS => {
+            let position= PositionBFS::new(puzzle.bottles.clone(), 0);
+            position.isValid().map_err(SolveError::InvalidPuzzle)?;
+            let mut bfs= BFS::new(position);
+            bfs.setLimits(options.limits);
+            bfs.setThreads(options.threads);
+            bfs.setRules(options.rules);
+            bfs.setProgress(options.progress);
+            (bfs.solve(), bfs.getStats())
+        },
+        Algorithm::Bidirectional => {
+            let position= PositionBFS::new(puzzle.bottles.clone(), 0);
+            position.isValid().map_err(SolveError::InvalidPuzzle)?;
+            // The backward search starts from solved positions, whose locks are not known
+            if puzzle.bottles.iter().any(Bottle::isLocked) {
+                return Err(SolveError::InvalidPuzzle("Error, bidirectional search cannot solve puzzles with locked bottles".to_string()));
+            }
+            let mut bidirectional= BidirectionalBFS::new(position);
+            bidirectional.setLimits(options.limits);
+            bidirectional.setRules(options.rules);
+            bidirectional.setProgress(options.progress);
+            (bidirectional.solve(), bidirectional.getStats())
+        },
+        Algorithm::Astar => {
+            let position= PositionAstar::new(puzzle.bottles.clone());
+            position.isValid().map_err(SolveError::InvalidPuzzle)?;
+            let mut astar= Astar::new(position);
+            astar.setLimits(options.limits);
+            astar.setHeuristic(options.heuristic);
+            astar.setRules(options.rules);
+            astar.setProgress(options.progress);
+            (astar.solve(), astar.getStats())
+        },
+        Algorithm::IDAstar => {
+            let position= PositionAstar::new(puzzle.bottles.clone());
+            position.isValid().map_err(SolveError::InvalidPuzzle)?;
+            let mut idastar= IDAstar::new(position);
+            idastar.setLimits(options.limits);
+            idastar.setTranspositionTable(options.transpositionTable);
+            idastar.setHeuristic(options.heuristic);
+            idastar.setRules(options.rules);
+            idastar.setProgress(options.progress);
+            (idastar.solve(), idastar.getStats())
+        },
+    };
+    outcomeToResult(positions, stats)
+        .map(|positions| Solution { lowerBound: positions.len() - 1, positions, algorithm: options.algorithm, stats })
+}
+
+/// Solves a puzzle with anytime A*: a first solution is found quickly with the weight given in
+/// `options` (5 if it is not above 1), then the search keeps looking for shorter ones until the best
+/// is proven to be the shortest possible or a limit in `options` is hit. The algorithm in `options`
+/// is ignored.
+///
+/// # Arguments
+/// * `puzzle` - The puzzle to solve.
+/// * `options` - The weight and limits of the search.
+/// * `onSolution` - Called with every solution shorter than the ones before, including the first.
+///
+/// # Returns
+/// `Ok(Solution)` with the shortest solution found, whose `lowerBound` tells whether it is proven to
+/// be optimal, or `Err(SolveError)` if the puzzle is invalid, cannot be solved, or a limit was hit
+/// before any solution was found.
+pub fn solveAnytime(puzzle: &Puzzle, options: &SolveOptions, mut onSolution: impl FnMut(&Solution)) -> Result<Solution, SolveError> {
+    let options= SolveOptions { weight: if options.weight > 1.0 { options.weight } else { DEFAULT_ANYTIME_WEIGHT }, ..options.clone() };
+    let mut anytime= newAnytimeAstar(puzzle, &options)?;
+    let positions= anytime.improve(|positions, lowerBound, stats| {
+        onSolution(&Solution { positions: positions.to_vec(), algorithm: Algorithm::Astar, stats, lowerBound: lowerBound as usize });
+    });
+    let stats= anytime.getStats();
+    outcomeToResult(positions, stats)
+        .map(|positions| Solution { positions, algorithm: Algorithm::Astar, stats, lowerBound: anytime.getLowerBound() as usize })
+}
+
+/// Weight of the first solution of `solveAnytime` when none is given
+const DEFAULT_ANYTIME_WEIGHT: f64 = 5.0;
+
+fn newAnytimeAstar(puzzle: &Puzzle, options: &SolveOptions) -> Result<AnytimeAstar, SolveError> {
+    if hasHiddenSlots(&puzzle.bottles) {
+        return Err(SolveError::InvalidPuzzle("Error, the puzzle has hidden slots. Use solveMystery to solve it interactively".to_string()));
+    }
+    let position= PositionAstar::new(puzzle.bottles.clone());
+    position.isValid().map_err(SolveError::InvalidPuzzle)?;
+    let mut anytime= AnytimeAstar::new(position, options.weight);
+    anytime.setLimits(options.limits);
+    anytime.setHeuristic(options.heuristic);
+    anytime.setRules(options.rules);
+    anytime.setProgress(options.progress);
+    Ok(anytime)
+}
+
+/// Maps the outcome of a search to the positions of the solution or the matching `SolveError`
+pub(crate) fn outcomeToResult(outcome: SearchOutcome, stats: SearchStats) -> Result<Vec<Rc<dyn Position>>, SolveError> {
+    match outcome {
+        SearchOutcome::Solved(positions) => Ok(positions),
+        SearchOutcome::Exhausted => Err(SolveError::NoSolution { stats }),
+        SearchOutcome::BudgetExceeded(limit, positions) => {
+            let partial= positions.iter().map(|position| position.getBottles().clone()).collect();
+            Err(SolveError::BudgetExceeded { limit, partial, stats })
+        },
+    }
+}
+
+/// Solves a puzzle with up to `maxTubes` extra empty bottles of the first bottle's size, as bought
+/// with the "add a tube" power-up of many games, using as few of them as possible. A tube added at
+/// the start allows every move it would allow later, so the search is run with 0, 1, 2... tubes
+/// added upfront until one is solved; the pours into and out of the extra tubes are the only moves
+/// the power-up adds. Each attempt runs `solve` with `options`, counting as unsolvable until the
+/// colors fit into the bottles.
+///
+/// # Returns
+/// `Ok(ExtraTubes)` with the fewest tubes that make the puzzle solvable and where they are first
+/// used, `Err(SolveError::NoSolution)` if not even `maxTubes` do, with the counters of all attempts,
+/// or another `Err(SolveError)` if the puzzle is invalid or a limit was hit.
+pub fn solveWithExtraTubes(puzzle: &Puzzle, options: &SolveOptions, maxTubes: usize) -> Result<ExtraTubes, SolveError> {
+    checkColorCounts(&puzzle.withExtraTubes(maxTubes).bottles).map_err(SolveError::InvalidPuzzle)?;
+    let mut stats= SearchStats::default();
+    for tubes in 0..=maxTubes {
+        let extended= puzzle.withExtraTubes(tubes);
+        match solve(&extended, options) {
+            Ok(mut solution) => {
+                stats.add(&solution.stats);
+                solution.stats= stats;
+                // With one tube less there is no solution, so every tube is used
+                let pours= solution.getPours();
+                let addBefore= (puzzle.bottles.len()..extended.bottles.len())
+                    .map(|tube| pours.iter().position(|pour| pour.to == tube).expect("Every extra tube must be used") + 1)
+                    .collect();
+                return Ok(ExtraTubes { puzzle: extended, solution, addBefore });
+            },
+            Err(SolveError::NoSolution { stats: attempt }) => stats.add(&attempt),
+            // Too few bottles for the colors yet
+            Err(SolveError::InvalidPuzzle(_)) if tubes < maxTubes => {},
+            Err(error) => return Err(error),
+        }
+    }
+    Err(SolveError::NoSolution { stats })
+}
+
+/// Computes a hint for a position, typically taken from the middle of a game: the first pour of an
+/// optimal continuation and how many moves remain. The search stops as soon as the optimal path is
+/// known; only its first step is kept.
+///
+/// # Arguments
+/// * `puzzle` - The current position.
+/// * `options` - The algorithm and other search settings.
+///
+/// # Returns
+/// `Ok(Hint)`, or `Err(SolveError)` if the position is invalid or cannot be solved.
+pub fn hint(puzzle: &Puzzle, options: &SolveOptions) -> Result<Hint, SolveError> {
+    let solution= solve(puzzle, options)?;
+    let pour= solution.positions
+        .get(1)
+        .map(|next| Pour::between(solution.positions[0].getBottles(), next.getBottles())
+             .expect("Consecutive positions must be one pour apart"));
+    Ok(Hint { pour, remainingMoves: solution.moveCount(), stats: solution.stats })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn solveBothAlgorithms() {
+        let puzzle= Puzzle::parse("ABCA;BCAB;CABC;;", None).unwrap();
+        let astar= solve(&puzzle, &SolveOptions { algorithm: Algorithm::Astar, ..SolveOptions::default() }).unwrap();
+        let bfs= solve(&puzzle, &SolveOptions { algorithm: Algorithm::BFS, ..SolveOptions::default() }).unwrap();
+        let idastar= solve(&puzzle, &SolveOptions { algorithm: Algorithm::IDAstar, transpositionTable: 100, ..SolveOptions::default() }).unwrap();
+        let bidirectional= solve(&puzzle, &SolveOptions { algorithm: Algorithm::Bidirectional, ..SolveOptions::default() }).unwrap();
+        assert_eq!(astar.moveCount(), bfs.moveCount());
+        assert_eq!(astar.moveCount(), idastar.moveCount());
+        assert_eq!(astar.moveCount(), bidirectional.moveCount());
+        assert_eq!(astar.getPours().len(), astar.moveCount());
+        assert!(astar.stats.expanded > 0 && astar.stats.generated >= astar.stats.expanded);
+        for solution in [&astar, &bfs, &idastar, &bidirectional] {
+            assert!(solution.stats.peakFrontier > 0 && solution.stats.peakMemory > 0, "{}", solution.algorithm.getName());
+        }
+        assert!(astar.positions.last().unwrap().getBottles().iter().all(|bottle| bottle.isSolved()));
+    }
+
+    #[test]
+    fn astarRequeuesShorterPaths() {
+        // The Towers estimate first reaches a position on the way with one move too many
+        let puzzle= Puzzle::parse("BAC;ABA;CCB;;", None).unwrap();
+        let bfs= solve(&puzzle, &SolveOptions { algorithm: Algorithm::BFS, ..SolveOptions::default() }).unwrap();
+        let astar= solve(&puzzle, &SolveOptions::default()).unwrap();
+        assert_eq!((astar.moveCount(), bfs.moveCount()), (6, 6));
+    }
+
+    #[test]
+    fn everyHeuristic() {
+        let puzzle= Puzzle::parse("ABCD;CDBA;BADC;DCAB;;", None).unwrap();
+        let shortest= solve(&puzzle, &SolveOptions { algorithm: Algorithm::BFS, ..SolveOptions::default() }).unwrap().moveCount();
+        for heuristic in Heuristic::ALL {
+            for algorithm in [Algorithm::Astar, Algorithm::IDAstar] {
+                let solution= solve(&puzzle, &SolveOptions { algorithm, heuristic, ..SolveOptions::default() }).unwrap();
+                assert_eq!(solution.moveCount(), shortest, "{} with {}", algorithm.getName(), heuristic.getName());
+            }
+        }
+        assert_eq!(Heuristic::fromName("parking"), Some(Heuristic::Parking));
+        assert_eq!(Heuristic::fromName("manhattan"), None);
+    }
+
+    #[test]
+    fn pruningKeepsShortest() {
+        let corpus= ["ABCD;CDBA;BADC;DCAB;;", "ABCA;BCAB;CABC;;", "AAB;ABB;;", "ABC;BCA;CAB;;", "AB  ;BA  ;AB  ;BA  ;    ",
+                     "ABCDE;EDCBA;CAEBD;DBACE;BEDAC;;", "AABB;BBAA;CCDD;DDCC;;", "ABAB;CDCD;BCDA;CDAB;;"];
+        for text in corpus {
+            let puzzle= Puzzle::parse(text, None).unwrap();
+            for algorithm in [Algorithm::BFS, Algorithm::Bidirectional, Algorithm::Astar, Algorithm::IDAstar] {
+                let unpruned= solve(&puzzle, &SolveOptions { algorithm, rules: Rules { pruning: false, ..Rules::default() }, ..SolveOptions::default() }).unwrap();
+                let pruned= solve(&puzzle, &SolveOptions { algorithm, ..SolveOptions::default() }).unwrap();
+                assert_eq!(pruned.moveCount(), unpruned.moveCount(), "{} on {}", algorithm.getName(), text);
+                if algorithm == Algorithm::BFS {
+                    assert!(pruned.stats.generated < unpruned.stats.generated, "{}", text);
+                }
+            }
+        }
+        // Bottles locked for some moves make pours that only permute the bottles worth waiting with
+        for text in ["AB@2;BA;;", "BA;AB@2;;", "AB;AB@4;;", "CA@3;CA;BB;;"] {
+            let puzzle= Puzzle::parse(text, None).unwrap();
+            for algorithm in [Algorithm::BFS, Algorithm::Astar, Algorithm::IDAstar] {
+                let unpruned= solve(&puzzle, &SolveOptions { algorithm, rules: Rules { pruning: false, ..Rules::default() }, ..SolveOptions::default() }).unwrap();
+                let pruned= solve(&puzzle, &SolveOptions { algorithm, ..SolveOptions::default() }).unwrap();
+                assert_eq!(pruned.moveCount(), unpruned.moveCount(), "{} on {}", algorithm.getName(), text);
+            }
+        }
+        // Too big for the other searches. A* used to miss the shortest solution here without pruning,
+        // as it dropped positions reached again with fewer moves
+        let puzzle= Puzzle::parse("GLKF;HGIF;KEBF;JLCD;EHJG;EBIB;LJCB;FHDK;ACKA;CAEG;DHDJ;IALI;;", None).unwrap();
+        for rules in [Rules { pruning: false, ..Rules::default() }, Rules::default()] {
+            assert_eq!(solve(&puzzle, &SolveOptions { rules, ..SolveOptions::default() }).unwrap().moveCount(), 39);
+        }
+    }
+
+    #[test]
+    fn singleUnitPours() {
+        for text in ["AAB;ABB;;", "ABC;BCA;CAB;;", "AB  ;BA  ;AB  ;BA  ;    "] {
+            let puzzle= Puzzle::parse(text, None).unwrap();
+            let run= solve(&puzzle, &SolveOptions::default()).unwrap().moveCount();
+            let unit= Rules { pruning: false, pour: PourRule::SingleUnit };
+            let shortest= solve(&puzzle, &SolveOptions { algorithm: Algorithm::BFS, rules: unit, ..SolveOptions::default() }).unwrap();
+            assert!(shortest.moveCount() >= run, "{}", text);
+            assert!(Pour::fromPositions(&shortest.positions).iter().all(|pour| pour.amount == 1), "{}", text);
+            for rules in [unit, Rules { pour: PourRule::SingleUnit, ..Rules::default() }] {
+                for algorithm in [Algorithm::BFS, Algorithm::Bidirectional, Algorithm::Astar, Algorithm::IDAstar] {
+                    for heuristic in Heuristic::ALL {
+                        let solution= solve(&puzzle, &SolveOptions { algorithm, heuristic, rules, ..SolveOptions::default() }).unwrap();
+                        assert_eq!(solution.moveCount(), shortest.moveCount(), "{} with {} on {}", algorithm.getName(), heuristic.getName(), text);
+                    }
+                }
+            }
+        }
+        // Every slot of the buried colors moves on its own
+        let puzzle= Puzzle::parse("AABB;BBAA;;", None).unwrap();
+        let rules= Rules { pour: PourRule::SingleUnit, ..Rules::default() };
+        assert_eq!(solve(&puzzle, &SolveOptions::default()).unwrap().moveCount(), 3);
+        assert_eq!(solve(&puzzle, &SolveOptions { rules, ..SolveOptions::default() }).unwrap().moveCount(), 6);
+    }
+
+    #[test]
+    fn wholeRunPours() {
+        for text in ["ABC;BCA;CAB;;", "AB ;AAB;B  ", "AB  ;BA  ;AB  ;BA  ;    ", "ABCA;BCAB;CABC;;"] {
+            let puzzle= Puzzle::parse(text, None).unwrap();
+            let whole= Rules { pour: PourRule::WholeRun, ..Rules::default() };
+            let shortest= solve(&puzzle, &SolveOptions { algorithm: Algorithm::BFS, rules: Rules { pruning: false, ..whole }, ..SolveOptions::default() }).unwrap();
+            // Every step is a pour of a whole run
+            for (step, pour) in Pour::fromPositions(&shortest.positions).iter().enumerate() {
+                let mut bottles= shortest.positions[step].getBottles().clone();
+                let mut source= bottles[pour.from].clone();
+                assert!(whole.pour(&mut bottles[pour.to], &mut source), "{}", text);
+                bottles[pour.from]= source;
+                assert_eq!(&bottles, shortest.positions[step + 1].getBottles(), "{}", text);
+            }
+            for algorithm in [Algorithm::Bidirectional, Algorithm::Astar, Algorithm::IDAstar] {
+                let solution= solve(&puzzle, &SolveOptions { algorithm, heuristic: Heuristic::Lookahead, rules: whole, ..SolveOptions::default() }).unwrap();
+                assert_eq!(solution.moveCount(), shortest.moveCount(), "{} on {}", algorithm.getName(), text);
+            }
+        }
+    }
+
+    #[test]
+    fn mixedSizes() {
+        // Tubes of 3 and 6 slots, the last color filling the tall one
+        let puzzle= Puzzle::parse("ACB;BCA;CCCCAB/6\n;/6", None).unwrap();
+        // A color split over two short tubes must still be gathered in the tall one
+        let split= Puzzle::parse("CCC;CCC;AAA/6;", None).unwrap();
+        for (puzzle, moves) in [(&puzzle, 7), (&split, 3)] {
+            for algorithm in [Algorithm::BFS, Algorithm::Bidirectional, Algorithm::Astar, Algorithm::IDAstar] {
+                for heuristic in Heuristic::ALL {
+                    let solution= solve(puzzle, &SolveOptions { algorithm, heuristic, ..SolveOptions::default() }).unwrap();
+                    assert_eq!(solution.moveCount(), moves, "{} with {}", algorithm.getName(), heuristic.getName());
+                    assert!(isSolvedPosition(solution.positions.last().unwrap().getBottles()));
+                }
+            }
+        }
+        assert!(matches!(solve(&Puzzle::parse("AAB;BBA/6", None).unwrap(), &SolveOptions::default()), Err(SolveError::InvalidPuzzle(_))));
+    }
+
+    #[test]
+    fn lockedBottles() {
+        // A locked bottle costs a move, or the solution if it waits for its own color
+        for (text, moves) in [("ABC;BCA;CAB;;", Some(7)), ("ABC;BCA;CAB@4;;", Some(8)), ("ABC;BCA;CAB@A;;", None)] {
+            let puzzle= Puzzle::parse(text, None).unwrap();
+            for algorithm in [Algorithm::BFS, Algorithm::Astar, Algorithm::IDAstar] {
+                for heuristic in Heuristic::ALL {
+                    let result= solve(&puzzle, &SolveOptions { algorithm, heuristic, ..SolveOptions::default() });
+                    assert_eq!(result.as_ref().ok().map(Solution::moveCount), moves, "{} {} with {}", text, algorithm.getName(), heuristic.getName());
+                }
+            }
+        }
+        let puzzle= Puzzle::parse("ABC;BCA;CAB@4;;", None).unwrap();
+        let solution= solve(&puzzle, &SolveOptions::default()).unwrap();
+        let pours= solution.getPours();
+        assert!(pours[..3].iter().all(|pour| pour.from != 2 && pour.to != 2));
+        assert!(matches!(solve(&puzzle, &SolveOptions { algorithm: Algorithm::Bidirectional, ..SolveOptions::default() }),
+                         Err(SolveError::InvalidPuzzle(_))));
+    }
+
+    #[test]
+    fn extraTubes() {
+        let puzzle= Puzzle::parse("BCA;ACB;BCA;", None).unwrap();
+        assert!(matches!(solveWithExtraTubes(&puzzle, &SolveOptions::default(), 0), Err(SolveError::NoSolution { .. })));
+        let result= solveWithExtraTubes(&puzzle, &SolveOptions::default(), 3).unwrap();
+        assert_eq!(result.count(), 1);
+        assert_eq!(result.puzzle.rowLayout, vec![4, 1]);
+        assert_eq!(result.solution.moveCount(), 8);
+        let step= result.addBefore[0];
+        assert_eq!(result.solution.getPours()[step - 1].to, 4);
+        assert!(result.solution.getPours()[..step - 1].iter().all(|pour| pour.from != 4 && pour.to != 4));
+        // Two colors of 3 with a single bottle of 3 only become valid with a tube
+        let result= solveWithExtraTubes(&Puzzle::parse("AAB;BBA/6", None).unwrap(), &SolveOptions::default(), 2).unwrap();
+        assert_eq!((result.count(), result.addBefore[0]), (1, 1));
+        let solvable= solveWithExtraTubes(&Puzzle::parse("BCA;ACB;BCA;;", None).unwrap(), &SolveOptions::default(), 2).unwrap();
+        assert_eq!((solvable.count(), solvable.solution.moveCount()), (0, 8));
+        assert!(matches!(solveWithExtraTubes(&Puzzle::parse("AB;BA@A", None).unwrap(), &SolveOptions::default(), 2),
+                         Err(SolveError::NoSolution { .. })));
+        assert!(matches!(solveWithExtraTubes(&Puzzle::parse("AB;B", None).unwrap(), &SolveOptions::default(), 2),
+                         Err(SolveError::InvalidPuzzle(_))));
+    }
+
+    #[test]
+    fn weightedAndAnytime() {
+        let puzzle= Puzzle::parse("ABCD;CDBA;BADC;DCAB;;", None).unwrap();
+        let optimal= solve(&puzzle, &SolveOptions::default()).unwrap();
+        assert_eq!(optimal.lowerBound, optimal.moveCount());
+        let weighted= solve(&puzzle, &SolveOptions { weight: 3.0, ..SolveOptions::default() }).unwrap();
+        assert!(weighted.lowerBound <= optimal.moveCount() && optimal.moveCount() <= weighted.moveCount());
+        let mut found= Vec::new();
+        let best= solveAnytime(&puzzle, &SolveOptions::default(), |solution| found.push(solution.moveCount())).unwrap();
+        assert_eq!((best.moveCount(), best.lowerBound), (optimal.moveCount(), optimal.moveCount()));
+        assert_eq!(found.last(), Some(&optimal.moveCount()));
+    }
+
+    #[test]
+    fn hintFirstMove() {
+        let puzzle= Puzzle::parse("ABCA;BCAB;CABC;;", None).unwrap();
+        let solution= solve(&puzzle, &SolveOptions::default()).unwrap();
+        let first= hint(&puzzle, &SolveOptions::default()).unwrap();
+        assert_eq!(first.remainingMoves, solution.moveCount());
+        // Following the hint leaves exactly one move less
+        let next= Puzzle::new(solution.positions[1].getBottles().clone(), puzzle.rowLayout.clone());
+        assert_eq!(hint(&next, &SolveOptions::default()).unwrap().remainingMoves, solution.moveCount() - 1);
+        assert!(first.pour.is_some());
+        let solved= Puzzle::parse("AAA;BBB;", None).unwrap();
+        let done= hint(&solved, &SolveOptions::default()).unwrap();
+        assert_eq!((done.pour, done.remainingMoves), (None, 0));
+    }
+
+    #[test]
+    fn solveErrors() {
+        let invalid= Puzzle::parse("AB;BA;BB;", None).unwrap();
+        assert!(matches!(solve(&invalid, &SolveOptions::default()), Err(SolveError::InvalidPuzzle(_))));
+        let stuck= Puzzle::parse("AB;BA", None).unwrap();
+        let error= solve(&stuck, &SolveOptions::default()).err().unwrap();
+        assert!(matches!(error, SolveError::NoSolution { .. }));
+        assert!(error.getStats().is_some_and(|stats| stats.expanded > 0));
+    }
+
+    #[test]
+    fn solveBudgetExceeded() {
+        let puzzle= Puzzle::parse("ABCA;BCAB;CABC;;", None).unwrap();
+        let limits= SearchLimits { maxExpanded: Some(3), ..SearchLimits::default() };
+        for algorithm in [Algorithm::Astar, Algorithm::BFS, Algorithm::IDAstar] {
+            let Err(SolveError::BudgetExceeded { limit, partial, stats })= solve(&puzzle, &SolveOptions { algorithm, limits, ..SolveOptions::default() }) else {
+                panic!("{} should run out of budget", algorithm.getName());
+            };
+            assert_eq!(limit, Limit::Expanded);
+            assert_eq!(stats.expanded, 3);
+            assert_eq!(partial[0], puzzle.bottles);
+            assert!(partial.windows(2).all(|pair| Pour::between(&pair[0], &pair[1]).is_some()));
+        }
+        // A generous budget does not change the result
+        let limits= SearchLimits { maxExpanded: Some(1_000_000), timeout: Some(std::time::Duration::from_secs(60)), maxMemory: Some(1 << 30) };
+        let solution= solve(&puzzle, &SolveOptions { limits, ..SolveOptions::default() }).unwrap();
+        assert_eq!(solution.moveCount(), solve(&puzzle, &SolveOptions::default()).unwrap().moveCount());
+    }
+}
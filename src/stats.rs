@@ -0,0 +1,113 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Counters describing how much work a search did
+pub struct SearchStats {
+    pub expanded: u64, // Positions whose successors were generated
+    pub generated: u64, // Successor positions produced
+    pub duplicates: u64, // Successors dropped because an equivalent position was already seen
+    pub peakFrontier: usize, // Most positions waiting to be expanded at once
+    pub peakMemory: usize, // Largest memory estimate of the search, in bytes
+    pub elapsed: Duration, // Wall-clock time of the search
+}
+
+impl SearchStats {
+    #[inline]
+    /// Keeps the size of the frontier and the memory estimate if they are the largest seen so far
+    pub fn recordPeaks(&mut self, frontier: usize, memory: usize) {
+        self.peakFrontier= self.peakFrontier.max(frontier);
+        self.peakMemory= self.peakMemory.max(memory);
+    }
+
+    /// Adds the counters of another search run after this one
+    pub fn add(&mut self, other: &SearchStats) {
+        self.expanded += other.expanded;
+        self.generated += other.generated;
+        self.duplicates += other.duplicates;
+        self.recordPeaks(other.peakFrontier, other.peakMemory);
+        self.elapsed += other.elapsed;
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expanded {}, generated {}, duplicates {}, peak frontier {}, peak memory {:.1} MiB, elapsed {:.3}s",
+               self.expanded, self.generated, self.duplicates, self.peakFrontier,
+               self.peakMemory as f64 / (1 << 20) as f64, self.elapsed.as_secs_f64())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Prints the counters of a running search to stderr, at most once per interval
+pub struct Progress {
+    interval: Option<Duration>, // `None` keeps the search quiet
+    nextReport: Option<Instant>,
+}
+
+impl Progress {
+    /// Constructs a new Progress reporting every `interval`, or never if it is `None`
+    pub fn new(interval: Option<Duration>) -> Self {
+        Self { interval, nextReport: None }
+    }
+
+    #[inline]
+    /// Returns `true` if nothing is reported
+    pub fn isQuiet(&self) -> bool {
+        self.interval.is_none()
+    }
+
+    /// Prints `stats` if the interval has passed since the previous report, or since the first call.
+    ///
+    /// # Arguments
+    /// * `name` - The search reporting, e.g. "A*".
+    /// * `stats` - The counters of the search so far, whose `elapsed` is replaced by the time since `start`.
+    /// * `start` - When the search started.
+    ///
+    /// # Returns
+    /// `true` if a report was printed.
+    pub fn report(&mut self, name: &str, stats: &SearchStats, start: Instant) -> bool {
+        let Some(interval)= self.interval else {
+            return false;
+        };
+        let now= Instant::now();
+        match self.nextReport {
+            Some(nextReport) if now >= nextReport => {
+                eprintln!("{} progress: {}", name, SearchStats { elapsed: stats.elapsed + start.elapsed(), ..*stats });
+                self.nextReport= Some(now + interval);
+                true
+            },
+            Some(_) => false,
+            None => {
+                self.nextReport= Some(now + interval);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn peaksAndReports() {
+        let mut stats= SearchStats::default();
+        stats.recordPeaks(10, 3 << 20);
+        stats.recordPeaks(5, 4 << 20);
+        assert_eq!((stats.peakFrontier, stats.peakMemory), (10, 4 << 20));
+        assert_eq!(stats.to_string(), "expanded 0, generated 0, duplicates 0, peak frontier 10, peak memory 4.0 MiB, elapsed 0.000s");
+        let start= Instant::now();
+        let mut quiet= Progress::default();
+        assert!(quiet.isQuiet());
+        assert!(!quiet.report("A*", &stats, start));
+        // The first call only starts the clock
+        let mut progress= Progress::new(Some(Duration::ZERO));
+        assert!(!progress.report("A*", &stats, start));
+        assert!(progress.report("A*", &stats, start));
+        let mut progress= Progress::new(Some(Duration::from_secs(3600)));
+        assert!(!progress.report("A*", &stats, start));
+        assert!(!progress.report("A*", &stats, start));
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+use crate::bottle::*;
+use crate::puzzle::*;
+use crate::rules::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A pour read from a move list. Bottle indices are 0-based; the text uses 1-based numbers
+pub struct PlannedPour {
+    pub from: usize,
+    pub to: usize,
+    pub amount: Option<usize>, // Expected number of units, if the move list states it
+    pub color: Option<u8>, // Expected color, if the move list states it
+    pub line: usize, // 1-based line number in the move list
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Why a pour cannot be made
+pub enum IllegalPour {
+    NoSuchBottle(usize),
+    SameBottle,
+    Locked(usize),
+    EmptySource,
+    FullDestination,
+    ColorMismatch { source: u8, destination: u8 },
+    RunDoesNotFit, // Only whole runs may be poured, and this one does not fit
+    UnexpectedAmount { expected: usize, actual: usize },
+    UnexpectedColor { expected: u8, actual: u8 },
+}
+
+impl fmt::Display for IllegalPour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IllegalPour::NoSuchBottle(index) => write!(f, "there is no bottle {}", index + 1),
+            IllegalPour::SameBottle => write!(f, "a bottle cannot be poured into itself"),
+            IllegalPour::Locked(index) => write!(f, "bottle {} is still locked", index + 1),
+            IllegalPour::EmptySource => write!(f, "the source bottle is empty"),
+            IllegalPour::FullDestination => write!(f, "the destination bottle is full"),
+            IllegalPour::ColorMismatch { source, destination } =>
+                write!(f, "color mismatch, '{}' cannot be poured onto '{}'", *source as char, *destination as char),
+            IllegalPour::RunDoesNotFit => write!(f, "the whole run of the top color does not fit into the destination bottle"),
+            IllegalPour::UnexpectedAmount { expected, actual } =>
+                write!(f, "the move list expects {} units to be poured, but {} are", expected, actual),
+            IllegalPour::UnexpectedColor { expected, actual } =>
+                write!(f, "the move list expects '{}' to be poured, but it is '{}'", *expected as char, *actual as char),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The result of replaying a move list against a puzzle
+pub enum Verdict {
+    Solved, // Every pour was legal and the final position is solved
+    Unsolved(Vec<Bottle>), // Every pour was legal, but the final position, given here, is not solved
+    Illegal { step: usize, pour: PlannedPour, reason: IllegalPour }, // `step` is 1-based
+}
+
+/// Parses a move list, one pour per line. Accepted forms include the solver's own output
+/// ("3. pour 3 → 17 (2 units of D)") as well as terse ones like "3 17", "3->17" or "3,17".
+/// Bottle numbers are 1-based. Blank lines and lines starting with '#' are ignored.
+///
+/// # Returns
+/// `Ok(Vec<PlannedPour>)`, or `Err(String)` naming the first line that cannot be read.
+pub fn parseMoves(text: &str) -> Result<Vec<PlannedPour>, String> {
+    let mut pours= Vec::new();
+    for (index, raw_line) in text.lines().enumerate() {
+        let line= raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let pour= parseMove(line, index + 1)
+            .ok_or_else(|| format!("Error: Line {} is not a move: '{}'", index + 1, line))?;
+        pours.push(pour);
+    }
+    Ok(pours)
+}
+
+fn parseMove(line: &str, lineNumber: usize) -> Option<PlannedPour> {
+    // Drop a leading step number such as "12."
+    let line= match line.split_once('.') {
+        Some((step, rest)) if !step.is_empty() && step.trim().chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => line,
+    };
+    let (pourPart, details)= match line.split_once('(') {
+        Some((pour, details)) => (pour, Some(details.trim_end_matches(')'))),
+        None => (line, None),
+    };
+    let pourPart= pourPart.trim();
+    let pourPart= pourPart.strip_prefix("pour").unwrap_or(pourPart);
+    let numbers: Vec<&str>= pourPart
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '→' || c == '-' || c == '>')
+        .filter(|word| !word.is_empty())
+        .collect();
+    let [from, to]= numbers[..] else {
+        return None;
+    };
+    let (from, to)= (from.parse::<usize>().ok()?, to.parse::<usize>().ok()?);
+    if from == 0 || to == 0 {
+        return None;
+    }
+    let (mut amount, mut color)= (None, None);
+    if let Some(details)= details {
+        // "<amount> unit(s) of <color>"
+        let words: Vec<&str>= details.split_whitespace().collect();
+        let [count, _, "of", shade]= words[..] else {
+            return None;
+        };
+        amount= Some(count.parse().ok()?);
+        color= match shade.as_bytes() {
+            [shade] => Some(*shade),
+            _ => return None,
+        };
+    }
+    Some(PlannedPour { from: from - 1, to: to - 1, amount, color, line: lineNumber })
+}
+
+/// Replays a move list against a puzzle, making every pour as `rules` do (`Rules::pour`).
+///
+/// # Returns
+/// `Verdict::Illegal` for the first pour that cannot be made, otherwise whether the final position is solved.
+pub fn verify(puzzle: &Puzzle, pours: &[PlannedPour], rules: &Rules) -> Verdict {
+    let mut bottles= puzzle.bottles.clone();
+    for (index, pour) in pours.iter().enumerate() {
+        if let Err(reason)= applyPour(&mut bottles, pour, rules) {
+            return Verdict::Illegal { step: index + 1, pour: *pour, reason };
+        }
+    }
+    if isSolvedPosition(&bottles) {
+        Verdict::Solved
+    } else {
+        Verdict::Unsolved(bottles)
+    }
+}
+
+fn applyPour(bottles: &mut [Bottle], pour: &PlannedPour, rules: &Rules) -> Result<(), IllegalPour> {
+    for index in [pour.from, pour.to] {
+        if index >= bottles.len() {
+            return Err(IllegalPour::NoSuchBottle(index));
+        }
+    }
+    if pour.from == pour.to {
+        return Err(IllegalPour::SameBottle);
+    }
+    if let Some(index)= [pour.from, pour.to].into_iter().find(|&index| bottles[index].isLocked()) {
+        return Err(IllegalPour::Locked(index));
+    }
+    let (source, destination)= (&bottles[pour.from], &bottles[pour.to]);
+    if source.isEmpty() {
+        return Err(IllegalPour::EmptySource);
+    }
+    if destination.content[destination.getCapacity() - 1] != EMPTY {
+        return Err(IllegalPour::FullDestination);
+    }
+    let sourceColor= source.content[source.getTopIndex()];
+    let destinationColor= destination.content[destination.getTopIndex()];
+    if !destination.isEmpty() && destinationColor != sourceColor {
+        return Err(IllegalPour::ColorMismatch { source: sourceColor, destination: destinationColor });
+    }
+    if let Some(expected)= pour.color.filter(|&expected| expected != sourceColor) {
+        return Err(IllegalPour::UnexpectedColor { expected, actual: sourceColor });
+    }
+    let filledBefore= filledSlots(source);
+    let poured= if pour.from < pour.to {
+        let (left, right)= bottles.split_at_mut(pour.to);
+        rules.pour(&mut right[0], &mut left[pour.from])
+    } else {
+        let (left, right)= bottles.split_at_mut(pour.from);
+        rules.pour(&mut left[pour.to], &mut right[0])
+    };
+    // Every other reason was ruled out above
+    if !poured {
+        return Err(IllegalPour::RunDoesNotFit);
+    }
+    let actual= filledBefore - filledSlots(&bottles[pour.from]);
+    if let Some(expected)= pour.amount.filter(|&expected| expected != actual) {
+        return Err(IllegalPour::UnexpectedAmount { expected, actual });
+    }
+    passMove(bottles);
+    Ok(())
+}
+
+#[inline]
+fn filledSlots(bottle: &Bottle) -> usize {
+    bottle.getContent().iter().filter(|&&slot| slot != EMPTY).count()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::solver::*;
+
+    #[test]
+    fn parseMoveForms() {
+        let pours= parseMoves("# comment\n1. pour 3 → 17 (2 units of D)\n\n3 17\n3->17\n 3 , 17 \npour 3 -> 17 (1 unit of x)\n").unwrap();
+        assert_eq!(pours.len(), 5);
+        assert_eq!(pours[0], PlannedPour { from: 2, to: 16, amount: Some(2), color: Some(b'D'), line: 2 });
+        assert!(pours[1..4].iter().all(|pour| (pour.from, pour.to, pour.amount, pour.color) == (2, 16, None, None)));
+        assert_eq!((pours[4].amount, pours[4].color), (Some(1), Some(b'x')));
+        assert!(parseMoves("3").is_err());
+        assert!(parseMoves("0 1").is_err());
+        assert_eq!(parseMoves("1 2\nfoo").unwrap_err(), "Error: Line 2 is not a move: 'foo'");
+    }
+
+    #[test]
+    fn verifySolverOutput() {
+        let puzzle= Puzzle::parse("ABCA;BCAB;CABC;;", None).unwrap();
+        let solution= solve(&puzzle, &SolveOptions::default()).unwrap();
+        let text: String= solution.getPours().iter().enumerate().map(|(i, pour)| format!("{}. {}\n", i + 1, pour)).collect();
+        let pours= parseMoves(&text).unwrap();
+        assert_eq!(verify(&puzzle, &pours, &Rules::default()), Verdict::Solved);
+        assert!(matches!(verify(&puzzle, &pours[..1], &Rules::default()), Verdict::Unsolved(_)));
+    }
+
+    #[test]
+    fn verifyPourRules() {
+        let puzzle= Puzzle::parse("ABB;BAA;;", None).unwrap();
+        let unit= Rules { pour: PourRule::SingleUnit, ..Rules::default() };
+        let solution= solve(&puzzle, &SolveOptions { rules: unit, ..SolveOptions::default() }).unwrap();
+        let text: String= solution.getPours().iter().map(|pour| format!("{}\n", pour)).collect();
+        let pours= parseMoves(&text).unwrap();
+        assert_eq!(verify(&puzzle, &pours, &unit), Verdict::Solved);
+        assert!(matches!(verify(&puzzle, &pours, &Rules::default()), Verdict::Illegal { reason: IllegalPour::UnexpectedAmount { .. }, .. }));
+        // Only one of the two B's fits into the second bottle
+        let puzzle= Puzzle::parse("ABB;AB;A;", None).unwrap();
+        let whole= Rules { pour: PourRule::WholeRun, ..Rules::default() };
+        let moves= parseMoves("1 2").unwrap();
+        assert!(matches!(verify(&puzzle, &moves, &Rules::default()), Verdict::Unsolved(_)));
+        assert!(matches!(verify(&puzzle, &moves, &whole), Verdict::Illegal { step: 1, reason: IllegalPour::RunDoesNotFit, .. }));
+    }
+
+    #[test]
+    fn reportIllegalPours() {
+        let puzzle= Puzzle::parse("AB;BA;AB;;", None).unwrap();
+        let check= |moves: &str| match verify(&puzzle, &parseMoves(moves).unwrap(), &Rules::default()) {
+            Verdict::Illegal { step, reason, .. } => Some((step, reason)),
+            _ => None,
+        };
+        assert_eq!(check("1 4\n4 4"), Some((2, IllegalPour::SameBottle)));
+        assert_eq!(check("9 1"), Some((1, IllegalPour::NoSuchBottle(8))));
+        assert_eq!(check("4 1"), Some((1, IllegalPour::EmptySource)));
+        assert_eq!(check("1 2"), Some((1, IllegalPour::FullDestination)));
+        assert_eq!(check("1 4\n2 4"), Some((2, IllegalPour::ColorMismatch { source: b'A', destination: b'B' })));
+        assert_eq!(check("1 4 (2 units of B)"), Some((1, IllegalPour::UnexpectedAmount { expected: 2, actual: 1 })));
+        assert_eq!(check("1 4 (1 unit of A)"), Some((1, IllegalPour::UnexpectedColor { expected: b'A', actual: b'B' })));
+        // The last bottle opens after two moves
+        let puzzle= Puzzle::parse("AB;BA;;@2", None).unwrap();
+        let verdict= verify(&puzzle, &parseMoves("1 3\n1 4").unwrap(), &Rules::default());
+        assert!(matches!(verdict, Verdict::Illegal { step: 2, reason: IllegalPour::Locked(3), .. }));
+        assert!(matches!(verify(&puzzle, &parseMoves("1 3\n2 1\n2 4").unwrap(), &Rules::default()), Verdict::Unsolved(_)));
+    }
+}
@@ -0,0 +1,171 @@
+use std::io::{self, BufRead, Write};
+use crate::bottle::*;
+use crate::pour::*;
+use crate::solver::*;
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const SOURCE_COLOR: &str = "\x1b[1;31m";
+const DESTINATION_COLOR: &str = "\x1b[1;32m";
+const RESET: &str = "\x1b[0m";
+
+/// A command typed by the user while walking through a solution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Next,
+    Previous,
+    First,
+    Last,
+    Jump(usize),
+    Quit,
+    Unknown,
+}
+
+impl Command {
+    fn parse(line: &str) -> Command {
+        let line= line.trim();
+        if let Ok(step)= line.parse::<usize>() {
+            return Command::Jump(step);
+        }
+        let mut words= line.split_whitespace();
+        match (words.next(), words.next()) {
+            (None, _) | (Some("n"), None) => Command::Next,
+            (Some("p"), None) => Command::Previous,
+            (Some("f"), None) => Command::First,
+            (Some("l"), None) => Command::Last,
+            (Some("q"), None) => Command::Quit,
+            (Some("g"), Some(step)) => step.parse().map(Command::Jump).unwrap_or(Command::Unknown),
+            _ => Command::Unknown,
+        }
+    }
+}
+
+/// Shows a solution one board at a time and lets the user move between the steps.
+///
+/// Commands are read line by line: Enter or `n` for the next step, `p` for the previous one, `f` and
+/// `l` for the first and last step, a step number or `g <step>` to jump, and `q` to quit. The
+/// source and destination bottles of the pour leading to the current board are highlighted.
+///
+/// # Arguments
+/// * `solution` - The solution to walk through.
+/// * `rowLayout` - How many bottles each input row holds.
+/// * `input` - Where commands are read from, usually the terminal.
+/// * `output` - Where boards are drawn, usually standard output.
+pub fn walkThrough(solution: &Solution, rowLayout: &[usize], mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let pours= solution.getPours();
+    let lastStep= solution.moveCount();
+    let mut step= 0;
+    let mut message= String::new();
+    loop {
+        write!(output, "{}", CLEAR_SCREEN)?;
+        drawStep(solution, &pours, rowLayout, step, &mut output)?;
+        if !message.is_empty() {
+            writeln!(output, "{}", message)?;
+            message.clear();
+        }
+        write!(output, "[Enter/n]ext [p]revious [f]irst [l]ast [g <step>] [q]uit > ")?;
+        output.flush()?;
+        let mut line= String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(());
+        }
+        match Command::parse(&line) {
+            Command::Next if step < lastStep => step += 1,
+            Command::Next => message= "Already at the last step".to_string(),
+            Command::Previous if step > 0 => step -= 1,
+            Command::Previous => message= "Already at the first step".to_string(),
+            Command::First => step= 0,
+            Command::Last => step= lastStep,
+            Command::Jump(target) if target <= lastStep => step= target,
+            Command::Jump(target) => message= format!("There is no step {}, the last one is {}", target, lastStep),
+            Command::Quit => return Ok(()),
+            Command::Unknown => message= format!("Unknown command '{}'", line.trim()),
+        }
+    }
+}
+
+/// Draws a single step: a header with the pour and the remaining move count, then the board
+fn drawStep(solution: &Solution, pours: &[Pour], rowLayout: &[usize], step: usize, output: &mut impl Write) -> io::Result<()> {
+    let lastStep= solution.moveCount();
+    let pour= if step > 0 { Some(pours[step - 1]) } else { None };
+    match pour {
+        Some(pour) => writeln!(output, "Step {}/{}: pour {}{}{} → {}{}{} ({} unit{} of {})",
+                               step, lastStep,
+                               SOURCE_COLOR, pour.from + 1, RESET,
+                               DESTINATION_COLOR, pour.to + 1, RESET,
+                               pour.amount, if pour.amount == 1 { "" } else { "s" }, pour.color as char)?,
+        None => writeln!(output, "Step 0/{}: initial position", lastStep)?,
+    }
+    writeln!(output, "Moves remaining: {}", lastStep - step)?;
+    writeln!(output)?;
+    let bottles= solution.positions[step].getBottles();
+    let mut rowStart= 0;
+    for rowLength in rowLayout {
+        let row= &bottles[rowStart..rowStart + rowLength];
+        let height= row.iter().map(Bottle::getCapacity).max().unwrap_or(0);
+        for i in (0..height).rev() {
+            for (j, bottle) in row.iter().enumerate() {
+                let index= rowStart + j;
+                // Shorter bottles have no slots up here
+                if i >= bottle.getCapacity() {
+                    write!(output, "   ")?;
+                    continue;
+                }
+                let slot= bottle.content[i] as char;
+                match pour {
+                    Some(pour) if pour.from == index => write!(output, "{}❚{}❚{}", SOURCE_COLOR, slot, RESET)?,
+                    Some(pour) if pour.to == index => write!(output, "{}❚{}❚{}", DESTINATION_COLOR, slot, RESET)?,
+                    _ => write!(output, "|{}|", slot)?,
+                }
+            }
+            writeln!(output)?;
+        }
+        if row.iter().any(Bottle::isLocked) {
+            for bottle in row {
+                write!(output, "{:^3}", bottle.lock)?;
+            }
+            writeln!(output)?;
+        }
+        // Bottle numbers under each row, so the pour in the header is easy to find
+        for j in 0..*rowLength {
+            write!(output, "{:^3}", (rowStart + j + 1) % 100)?;
+        }
+        writeln!(output)?;
+        writeln!(output)?;
+        rowStart += rowLength;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::puzzle::*;
+
+    #[test]
+    fn parseCommands() {
+        assert_eq!(Command::parse("\n"), Command::Next);
+        assert_eq!(Command::parse("n"), Command::Next);
+        assert_eq!(Command::parse(" p "), Command::Previous);
+        assert_eq!(Command::parse("12"), Command::Jump(12));
+        assert_eq!(Command::parse("g 3"), Command::Jump(3));
+        assert_eq!(Command::parse("g x"), Command::Unknown);
+        assert_eq!(Command::parse("q"), Command::Quit);
+    }
+
+    #[test]
+    fn walkSteps() {
+        let puzzle= Puzzle::parse("AB;BA;", None).unwrap();
+        let solution= solve(&puzzle, &SolveOptions::default()).unwrap();
+        let mut output= Vec::new();
+        walkThrough(&solution, &puzzle.rowLayout, "n\nl\nn\ng 9\np\nq\n".as_bytes(), &mut output).unwrap();
+        let output= String::from_utf8(output).unwrap();
+        assert!(output.contains("Step 0/3: initial position"));
+        assert!(output.contains("Step 1/3: "));
+        assert!(output.contains("Moves remaining: 0"));
+        assert!(output.contains("Already at the last step"));
+        assert!(output.contains("There is no step 9, the last one is 3"));
+        assert!(output.contains("Step 2/3: "));
+    }
+}